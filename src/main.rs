@@ -1,17 +1,22 @@
 use anyhow::{bail, Context, Result};
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::{generate, Shell};
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::OnceLock;
 use tokio::process::Command;
 
 const IMAGE_NAME: &str = "claude-code-sandbox";
 const DEFAULT_SESSION: &str = "claude";
 const CONTAINER_PREFIX: &str = "claude";
+/// Label applied to every sandbox container so they can be found with `docker ps --filter`
+const SANDBOX_LABEL: &str = "claude-sandbox=true";
+/// Label holding the RFC 3339 expiry time of containers started with `--ttl`
+const EXPIRES_LABEL: &str = "claude-sandbox.expires";
 
 /// Official Anthropic plugin marketplace.
 const PLUGIN_MARKETPLACE_NAME: &str = "claude-plugins-official";
@@ -56,50 +61,251 @@ Port formats for -p:
   8080:3000         Map host 8080 to container 3000
   127.0.0.1:8080:3000  Bind to specific IP")]
 struct Cli {
+    /// Config directory to use (overrides CLAUDE_SANDBOX_CONFIG and ~/.claude-sandbox)
+    #[arg(long, global = true, value_name = "PATH")]
+    config_dir: Option<PathBuf>,
+    /// Print each docker command to stderr before running it
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Docker context to run against (overrides `docker_context` in config.toml)
+    #[arg(long, global = true, value_name = "NAME")]
+    context: Option<String>,
+    /// Answer yes to every confirmation prompt (including --dangerously-skip-permissions)
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// Never prompt: take the safe default (abort destructive actions, keep
+    /// the current state otherwise)
+    #[arg(long, global = true, conflicts_with = "yes")]
+    no_interactive: bool,
+    /// Key sequence that detaches from an interactive session, e.g. "ctrl-x,x"
+    /// (overrides `detach_keys` in config.toml; docker's default is ctrl-p,ctrl-q)
+    #[arg(long, global = true, value_name = "SPEC")]
+    detach_keys: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Start Claude Code with mapped folders
     Run {
         /// Folders to map into the session
-        #[arg(required = true)]
+        #[arg(required_unless_present_any = ["scratch", "copy_in"])]
         folders: Vec<PathBuf>,
+        /// Copy a folder into the workspace instead of bind-mounting it, so the
+        /// original is never modified. Honors a .claude-sandbox-ignore file
+        #[arg(long, value_name = "FOLDER")]
+        copy_in: Vec<PathBuf>,
+        /// Use an in-memory (tmpfs) workspace instead of mapped folders.
+        /// Nothing in the workspace is saved when the container stops
+        #[arg(long, conflicts_with_all = ["folders", "alias"])]
+        scratch: bool,
+        /// Mount a host directory shared by all sandboxes run with this flag
+        /// at /home/claude/shared
+        #[arg(long)]
+        shared_tmp: bool,
+        /// Don't mount the shared global .claude, .claude.json and .config state
+        /// (the container needs fresh auth; conversations stay per-container)
+        #[arg(long)]
+        no_default_mounts: bool,
+        /// Don't mount the `default_mounts` folders from config.toml
+        #[arg(long)]
+        no_default_folders: bool,
+        /// Start Claude in this folder of the workspace (e.g. one of several mapped folders)
+        #[arg(long, value_name = "SUBDIR")]
+        cwd: Option<String>,
+        /// Keep-alive command for the container instead of the image's
+        /// (e.g. "sleep infinity"). Split into words like a POSIX shell, so
+        /// quotes group arguments. It must keep running, or later commands
+        /// cannot exec into the container
+        #[arg(long, value_name = "COMMAND")]
+        entrypoint: Option<String>,
         /// Initial prompt to send to Claude
         #[arg(short = 'm', long)]
         prompt: Option<String>,
         /// Path to a file containing the initial prompt
         #[arg(short = 'f', long)]
         prompt_file: Option<PathBuf>,
+        /// Prompt template file with {{KEY}} placeholders filled from --var
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["prompt", "prompt_file"])]
+        prompt_template: Option<PathBuf>,
+        /// Template variable (KEY=VALUE) for --prompt-template
+        #[arg(long = "var", value_name = "KEY=VALUE", requires = "prompt_template")]
+        vars: Vec<String>,
+        /// Leave unknown placeholders in the template instead of failing
+        #[arg(long, requires = "prompt_template")]
+        allow_unfilled: bool,
         /// Named session (creates new conversation, can be resumed with continue -n)
         #[arg(short, long)]
         name: Option<String>,
         /// Override container name (default: derived from folder names)
         #[arg(long)]
         container: Option<String>,
+        /// Image to run instead of the default (e.g. a variant built with `build --tag`)
+        #[arg(long)]
+        image: Option<String>,
+        /// Short alias to refer to this container in other commands
+        #[arg(long)]
+        alias: Option<String>,
+        /// Free-text note shown by list, status and describe (see `note`)
+        #[arg(long, conflicts_with = "scratch")]
+        note: Option<String>,
         /// Memory limit (e.g., "4g")
-        #[arg(short, long)]
+        // `-m` is taken by --prompt
+        #[arg(long)]
         memory: Option<String>,
+        /// Total memory + swap limit (e.g., "6g", or "-1" for unlimited swap)
+        #[arg(long, value_name = "LIMIT", allow_hyphen_values = true)]
+        memory_swap: Option<String>,
+        /// Soft memory limit enforced only under host memory pressure
+        /// (e.g., "2g"; must not exceed --memory)
+        #[arg(long, value_name = "LIMIT")]
+        memory_reservation: Option<String>,
         /// CPU limit (e.g., "2")
         #[arg(long)]
         cpus: Option<String>,
+        /// CPUs the container may run on (e.g., "0-3" or "0,2")
+        #[arg(long, value_name = "SPEC")]
+        cpuset_cpus: Option<String>,
+        /// Maximum number of processes in the container
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        pids_limit: Option<u64>,
         /// Expose container ports to host. Formats: PORT | HOST:CONTAINER | IP:HOST:CONTAINER
         #[arg(short = 'p', long = "port", value_name = "[HOST:]PORT")]
         ports: Vec<String>,
+        /// Add a custom host-to-IP mapping (NAME:IP, IP may be "host-gateway")
+        #[arg(long, value_name = "NAME:IP")]
+        add_host: Vec<String>,
+        /// HTTP(S) proxy URL for the container (and the image build, if needed)
+        #[arg(long, value_name = "URL")]
+        proxy: Option<String>,
+        /// Pass the host's HTTP_PROXY/HTTPS_PROXY/NO_PROXY into the container
+        #[arg(long)]
+        inherit_proxy: bool,
         /// Additional environment variables (KEY=VALUE)
         #[arg(short, long)]
         env: Vec<String>,
+        /// Docker label to set on the container (KEY=VALUE), usable with --label-filter
+        #[arg(long, value_name = "KEY=VALUE")]
+        label: Vec<String>,
+        /// File of KEY=VALUE label lines (# comments allowed); --label wins on conflicts
+        #[arg(long, value_name = "PATH")]
+        label_file: Option<PathBuf>,
+        /// Environment variable read from a file (KEY=@path); the value is never shown
+        #[arg(long, value_name = "KEY=@PATH")]
+        env_secret: Vec<String>,
+        /// Read ANTHROPIC_API_KEY from a file instead of the host environment
+        #[arg(long, value_name = "PATH")]
+        api_key_file: Option<PathBuf>,
+        /// Read ANTHROPIC_API_KEY from this macOS Keychain item (service name)
+        #[arg(long, value_name = "NAME", conflicts_with = "api_key_file")]
+        api_key_keychain: Option<String>,
         /// Run in dangerously skip permissions mode
         #[arg(long)]
         dangerously_skip_permissions: bool,
+        /// Reuse the host's claude-code login (~/.claude.json and credentials)
+        #[arg(long)]
+        import_auth: bool,
         /// Continue the most recent conversation
         #[arg(short, long)]
         continue_session: bool,
         /// Resume a specific conversation by ID
         #[arg(short, long)]
         resume: Option<String>,
+        /// Import conversations from an archive (a tar of a `conversations/`
+        /// directory) and resume the most recent one
+        #[arg(long, value_name = "ARCHIVE", conflicts_with_all = ["continue_session", "resume"])]
+        from_export: Option<PathBuf>,
+        /// Copy a conversation from any sandbox's history into this container
+        /// and resume it at its last turn
+        #[arg(
+            long,
+            value_name = "CONVERSATION_ID",
+            conflicts_with_all = ["continue_session", "resume", "from_export"]
+        )]
+        replay: Option<String>,
+        /// Restart policy: no, on-failure[:N], unless-stopped or always
+        #[arg(long = "restart", value_name = "POLICY")]
+        restart_policy: Option<String>,
+        /// Run as the host user's UID/GID so files created in mapped folders are owned by you
+        #[arg(long)]
+        match_user: bool,
+        /// Add the container user to a host group (numeric GID or group name)
+        #[arg(long, value_name = "GROUP")]
+        group_add: Vec<String>,
+        /// Refuse to create a container if this many sandboxes are already running
+        #[arg(long)]
+        max_containers: Option<usize>,
+        /// Stop all other running sandboxes first (they are kept, not removed)
+        #[arg(long)]
+        stop_others: bool,
+        /// Let `reap` stop and remove the container after this long (e.g. "8h", "2d")
+        #[arg(long, value_name = "DURATION")]
+        ttl: Option<String>,
+        /// Only attach to an already-running container; never build or create one
+        #[arg(long)]
+        attach_only: bool,
+        /// Stop, remove and recreate the container even if it is running
+        /// (conversation history is kept, running processes are lost)
+        #[arg(long, conflicts_with = "attach_only")]
+        recreate: bool,
+        /// Fail if `docker exec` can't reach the container within SECS
+        #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+        attach_timeout: Option<u64>,
+        /// Run the prompt non-interactively and emit claude's output as JSON events
+        #[arg(long)]
+        json_logs: bool,
+        /// Run the prompt with `claude --print` (no TTY), print the response and exit
+        #[arg(long, conflicts_with_all = ["json_logs", "stream_prompt"])]
+        print: bool,
+        /// After the session ends, ask Claude for a short recap and print it
+        #[arg(long, conflicts_with_all = ["json_logs", "print"])]
+        summary: bool,
+        /// Don't print the session banner before attaching
+        #[arg(long)]
+        no_banner: bool,
+        /// Append JSON events to this file instead of stdout (with --json-logs)
+        #[arg(long, value_name = "FILE", requires = "json_logs")]
+        log_file: Option<PathBuf>,
+        /// Command to run in the container after it is created (e.g. "npm ci")
+        #[arg(long, value_name = "COMMAND")]
+        post_start: Option<String>,
+        /// Attach even if the post-start command fails
+        #[arg(long, requires = "post_start")]
+        post_start_ignore_errors: bool,
+        /// Don't mark the workspace repos as git safe.directory in a new container
+        #[arg(long)]
+        no_git_safe: bool,
+        /// Command to run in the container after the interactive session ends (e.g. "cargo test")
+        #[arg(long, value_name = "COMMAND")]
+        on_exit: Option<String>,
+        /// Copy the container's output directory to this host folder when the session ends
+        #[arg(long, value_name = "HOST_PATH")]
+        output_dir: Option<PathBuf>,
+        /// Container directory collected by --output-dir
+        #[arg(
+            long,
+            value_name = "CONTAINER_PATH",
+            default_value = DEFAULT_OUTPUT_PATH,
+            requires = "output_dir"
+        )]
+        output_from: String,
+        /// Stream Claude's response to the initial prompt, then attach interactively
+        #[arg(long)]
+        stream_prompt: bool,
+        /// Disable all networking (--network none). Claude itself needs network
+        /// access to reach the API, so this is only useful for offline tasks
+        /// or together with a local proxy
+        #[arg(long)]
+        isolated: bool,
+        /// Seccomp profile (JSON file) to apply instead of Docker's default
+        #[arg(long, value_name = "PROFILE")]
+        seccomp: Option<PathBuf>,
+        /// Extra `docker run --security-opt` value (repeatable), e.g.
+        /// "no-new-privileges" or "apparmor=my-profile"
+        #[arg(long, value_name = "OPT")]
+        security_opt: Vec<String>,
     },
     /// Continue a session by folder path or container name
     Continue {
@@ -109,6 +315,20 @@ enum Commands {
         /// Named session to resume (omit to continue most recent conversation)
         #[arg(short, long)]
         name: Option<String>,
+        /// Start Claude in this folder of the workspace
+        #[arg(long, value_name = "SUBDIR")]
+        cwd: Option<String>,
+        /// Open a bash shell instead if Claude fails right away (e.g. no
+        /// conversation to continue)
+        #[arg(long)]
+        shell: bool,
+        /// Send this prompt to the conversation without a TTY, print the
+        /// response and exit
+        #[arg(short = 'm', long, conflicts_with = "shell")]
+        prompt: Option<String>,
+        /// After the session ends, ask Claude for a short recap and print it
+        #[arg(long, conflicts_with = "prompt")]
+        summary: bool,
     },
     /// Resume a specific conversation by ID
     Resume {
@@ -117,24 +337,179 @@ enum Commands {
         /// Folder path or container name
         #[arg(short, long)]
         target: Option<String>,
+        /// List the container's conversations instead of opening the picker
+        #[arg(long, conflicts_with = "conversation_id")]
+        list: bool,
+        /// With --list, print the conversations as JSON
+        #[arg(long, requires = "list")]
+        json: bool,
+    },
+    /// Search a container's conversations for a word or phrase
+    Search {
+        /// Text to look for (case-insensitive)
+        query: String,
+        /// Folder path, alias, or container name
+        #[arg(short, long)]
+        target: Option<String>,
+        /// Print the matches as JSON
+        #[arg(long)]
+        json: bool,
     },
     /// Open a shell in a container
     Shell {
         /// Folder path or container name
         target: Option<String>,
+        /// Print the command's stdout, stderr and exit code as JSON
+        #[arg(long, requires = "command")]
+        json: bool,
+        /// Run as root (e.g. to try out a package before adding it to the Dockerfile)
+        #[arg(long)]
+        root: bool,
+        /// Command to run instead of an interactive shell (after `--`)
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+    /// Re-run a command in a container whenever files in mapped host folders change
+    WatchCmd {
+        /// Folder path, alias, or container name
+        target: Option<String>,
+        /// Host path to watch (repeatable; default: the container's mapped folders)
+        #[arg(short, long = "path", value_name = "PATH")]
+        paths: Vec<PathBuf>,
+        /// Command to run in the container (after `--`)
+        #[arg(last = true, required = true)]
+        cmd: Vec<String>,
     },
     /// Stop a running container
     Stop {
         /// Folder path or container name (or "all" to stop all containers)
         target: Option<String>,
+        /// With "all", pick which containers to stop from a numbered list
+        #[arg(long)]
+        select: bool,
+        /// With "all", only stop containers running this image
+        #[arg(long)]
+        image: Option<String>,
+        /// With "all", only stop containers with this label (KEY or KEY=VALUE)
+        #[arg(long, value_name = "KEY=VALUE")]
+        label_filter: Vec<String>,
+        /// Seconds to wait for a graceful shutdown before killing (default: 10)
+        #[arg(short, long, value_name = "SECS")]
+        time: Option<u32>,
+        /// Kill immediately (docker kill) instead of a graceful stop
+        #[arg(long, conflicts_with = "time")]
+        kill: bool,
+        /// Keep the stopped container (and its state) so `start` can boot it again
+        #[arg(long)]
+        keep: bool,
+    },
+    /// Boot a container stopped with `stop --keep`, without recreating it
+    Start {
+        /// Folder path, alias, or container name
+        target: Option<String>,
+    },
+    /// Open a port published by a container in the browser
+    Connect {
+        /// Folder path or container name
+        target: Option<String>,
+        /// Container port to open (default: the first published port)
+        #[arg(short, long)]
+        port: Option<u16>,
+    },
+    /// Freeze all processes in a running container
+    Pause {
+        /// Folder path or container name
+        target: Option<String>,
+    },
+    /// Resume a paused container
+    Unpause {
+        /// Folder path or container name
+        target: Option<String>,
     },
     /// List all Claude sandbox sessions
-    List,
+    List {
+        /// Only show containers created within this duration (e.g. "2h", "7d")
+        #[arg(long, value_name = "DURATION")]
+        since: Option<String>,
+        /// Only show containers created more than this duration ago
+        #[arg(long, value_name = "DURATION")]
+        before: Option<String>,
+        /// Only show running containers
+        #[arg(long)]
+        running: bool,
+        /// Only show containers running this image
+        #[arg(long)]
+        image: Option<String>,
+        /// Only show containers with this label (KEY or KEY=VALUE, repeatable)
+        #[arg(long, value_name = "KEY=VALUE")]
+        label_filter: Vec<String>,
+        /// Only show containers for the current directory
+        #[arg(long)]
+        here: bool,
+        /// Print each container using a template, e.g. "{{.Name}}\t{{.Status}}\t{{.Folders}}".
+        /// Fields: Name, Alias, Status, State, Ports, CreatedAt, Folders, Note
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
+        /// Print only the number of matching containers
+        #[arg(long, conflicts_with = "format")]
+        count: bool,
+        /// Group the table by the value of this container label
+        #[arg(long, value_name = "LABEL_KEY", conflicts_with_all = ["format", "count"])]
+        group_by: Option<String>,
+        /// Order containers by name, created (newest first), status (running
+        /// first) or size (largest first)
+        #[arg(long, value_enum, default_value_t = ListSort::Created)]
+        sort: ListSort,
+        /// Reverse the sort order
+        #[arg(long)]
+        reverse: bool,
+    },
     /// Build or rebuild the Docker image
     Build {
         /// Force rebuild without cache
         #[arg(short, long)]
         no_cache: bool,
+        /// Hide Docker build output, printing only the resulting image ID and
+        /// the success line
+        #[arg(short, long)]
+        quiet: bool,
+        /// Emit build progress and the result as JSON lines on stdout
+        #[arg(long, conflicts_with = "quiet")]
+        json: bool,
+        /// Tag the image with this name instead of the default
+        #[arg(long, value_name = "IMAGE")]
+        tag: Option<String>,
+        /// Open the Dockerfile in $EDITOR before building (for this build only)
+        #[arg(long)]
+        edit: bool,
+        /// HTTP(S) proxy URL to use for network access during the build
+        #[arg(long, value_name = "URL")]
+        proxy: Option<String>,
+        /// Pass the host's HTTP_PROXY/HTTPS_PROXY/NO_PROXY to the build
+        #[arg(long)]
+        inherit_proxy: bool,
+        /// Remove dangling (untagged) images left behind by earlier builds
+        #[arg(long)]
+        prune_images: bool,
+        /// Automatically retry a failed build up to N times (completed steps are cached)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        retry: u32,
+        /// Set a Dockerfile ARG (KEY=VALUE, can be repeated)
+        #[arg(long = "build-arg", value_name = "KEY=VALUE")]
+        build_args: Vec<String>,
+    },
+    /// Export the sandbox image to a tar archive for sharing
+    SaveImage {
+        /// Path of the tar archive to write
+        out: PathBuf,
+        /// Image to save instead of the default
+        #[arg(long)]
+        image: Option<String>,
+    },
+    /// Import a sandbox image from a tar archive created by save-image
+    LoadImage {
+        /// Path of the tar archive to load
+        archive: PathBuf,
     },
     /// Reset Claude's persistent state
     Reset {
@@ -142,10 +517,131 @@ enum Commands {
         #[arg(short, long)]
         force: bool,
     },
+    /// Stop and remove sandboxes whose --ttl has expired (e.g. from cron)
+    Reap {
+        /// Only list the expired containers
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show where claude-sandbox keeps its state (registries, Dockerfile, ...)
+    Config {
+        /// Open the config directory in the system file manager
+        #[arg(long)]
+        open: bool,
+        /// Only print the config directory path
+        #[arg(long, conflicts_with = "open")]
+        path: bool,
+    },
+    /// Delete old conversations from a container's history
+    CleanConversations {
+        /// Folder path or container name
+        target: Option<String>,
+        /// Number of most recent conversations to keep
+        #[arg(long, default_value_t = 10)]
+        keep: usize,
+        /// Only delete conversations last modified longer ago than this (e.g. "30d")
+        #[arg(long, value_name = "DURATION")]
+        older_than: Option<String>,
+    },
+    /// Show a container's logs (docker logs), optionally filtered
+    Logs {
+        /// Folder path, alias, or container name
+        target: Option<String>,
+        /// Keep streaming new log lines
+        #[arg(short, long)]
+        follow: bool,
+        /// Only show the last N lines
+        #[arg(long, value_name = "N")]
+        tail: Option<u32>,
+        /// Only show lines containing TEXT, with the matches highlighted
+        #[arg(long, value_name = "TEXT")]
+        grep: Option<String>,
+        /// Show the lines that don't contain the --grep text instead
+        #[arg(long, requires = "grep")]
+        invert: bool,
+    },
+    /// Show the recorded command history of a container
+    History {
+        /// Folder path or container name
+        target: Option<String>,
+        /// Show the full prompt text instead of redacting it
+        #[arg(long)]
+        show_prompts: bool,
+    },
+    /// Summarize a sandbox: folders with their git branch and last commit,
+    /// what it was started for, and its conversations
+    Describe {
+        /// Folder path, alias, or container name
+        target: Option<String>,
+        /// Show the first prompt's text instead of redacting it
+        #[arg(long)]
+        show_prompts: bool,
+    },
+    /// Show, set or clear the free-text note of a container
+    Note {
+        /// Folder path, alias, or container name
+        target: String,
+        /// New note (omit to show the current one)
+        text: Option<String>,
+        /// Remove the note
+        #[arg(long, conflicts_with = "text")]
+        clear: bool,
+    },
     /// Show status of a container
     Status {
         /// Folder path or container name
         target: Option<String>,
+        /// Show a status line for every sandbox container
+        #[arg(long, conflicts_with = "target")]
+        all: bool,
+    },
+    /// Mount an additional folder into an existing container (recreates it)
+    AddFolder {
+        /// Folder path, alias, or container name
+        target: String,
+        /// Folder to add
+        folder: PathBuf,
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Show everything known about a container: registry entry, mounts,
+    /// ports, env (secrets hidden), image and live state
+    Info {
+        /// Folder path, alias, or container name
+        target: Option<String>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the env vars stored for a container (secret-looking values hidden)
+    Env {
+        /// Folder path, alias, or container name
+        target: Option<String>,
+    },
+    /// Set or remove stored env vars and recreate the container
+    SetEnv {
+        /// Folder path, alias, or container name
+        target: String,
+        /// Variables to set (KEY=VALUE)
+        #[arg(value_name = "KEY=VALUE")]
+        kv: Vec<String>,
+        /// Variables to remove
+        #[arg(long, value_name = "KEY")]
+        unset: Vec<String>,
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Start a sandbox together with the services from claude-sandbox.services.toml
+    Up {
+        /// Project folder containing claude-sandbox.services.toml
+        folder: PathBuf,
+    },
+    /// Stop and remove a sandbox started with `up`, its services and network
+    Down {
+        /// Project folder passed to `up`
+        folder: PathBuf,
     },
     /// Generate shell completions
     Completions {
@@ -153,12 +649,32 @@ enum Commands {
         #[arg(value_enum)]
         shell: Shell,
     },
+    /// Print container names, aliases and folder names for shell completion
+    #[command(name = "__complete_containers", hide = true)]
+    CompleteContainers,
 }
 
 #[derive(Deserialize)]
 struct ContainerInfo {
     #[serde(rename = "State")]
     state: ContainerState,
+    #[serde(rename = "NetworkSettings", default)]
+    network_settings: NetworkSettings,
+}
+
+#[derive(Deserialize, Default)]
+struct NetworkSettings {
+    /// Maps "<port>/<proto>" to its host bindings (null when not published)
+    #[serde(rename = "Ports", default)]
+    ports: HashMap<String, Option<Vec<PortBinding>>>,
+}
+
+#[derive(Deserialize)]
+struct PortBinding {
+    #[serde(rename = "HostIp", default)]
+    host_ip: String,
+    #[serde(rename = "HostPort")]
+    host_port: String,
 }
 
 #[derive(Deserialize)]
@@ -167,1452 +683,7789 @@ struct ContainerState {
     status: String,
     #[serde(rename = "Running")]
     running: bool,
+    #[serde(rename = "Paused", default)]
+    paused: bool,
+    #[serde(rename = "OOMKilled", default)]
+    oom_killed: bool,
+    #[serde(rename = "ExitCode", default)]
+    exit_code: i64,
 }
 
+#[derive(Default)]
 struct RunConfig {
     folders: Vec<PathBuf>,
+    copy_in: Vec<PathBuf>,
     prompt: Option<String>,
     prompt_file: Option<PathBuf>,
+    prompt_template: Option<PathBuf>,
+    vars: Vec<String>,
+    allow_unfilled: bool,
     session_name: Option<String>,
     container_override: Option<String>,
+    alias: Option<String>,
+    container: ContainerOptions,
+    dangerously_skip_permissions: bool,
+    import_auth: bool,
+    continue_session: bool,
+    resume: Option<String>,
+    from_export: Option<PathBuf>,
+    replay: Option<String>,
+    note: Option<String>,
+    label_file: Option<PathBuf>,
+    cwd: Option<String>,
+    max_containers: Option<usize>,
+    stop_others: bool,
+    attach_only: bool,
+    recreate: bool,
+    attach_timeout: Option<u64>,
+    stream_prompt: bool,
+    post_start: Option<String>,
+    post_start_ignore_errors: bool,
+    no_git_safe: bool,
+    on_exit: Option<String>,
+    output_dir: Option<PathBuf>,
+    output_from: String,
+    json_logs: bool,
+    print: bool,
+    summary: bool,
+    no_banner: bool,
+    log_file: Option<PathBuf>,
+}
+
+/// Options passed to `docker run` when a container is created. They are kept
+/// in the registry so `add-folder`/`set-env` can recreate the container as-is;
+/// env vars and the expiry live in the registry entry itself.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+struct ContainerOptions {
+    image: Option<String>,
     memory: Option<String>,
+    memory_swap: Option<String>,
+    memory_reservation: Option<String>,
     cpus: Option<String>,
+    cpuset_cpus: Option<String>,
+    pids_limit: Option<u64>,
     ports: Vec<String>,
+    add_hosts: Vec<String>,
+    #[serde(skip)]
     env_vars: Vec<String>,
-    dangerously_skip_permissions: bool,
-    continue_session: bool,
-    resume: Option<String>,
+    labels: Vec<String>,
+    env_secrets: Vec<String>,
+    api_key_file: Option<PathBuf>,
+    api_key_keychain: Option<String>,
+    /// Key read from one of the above before an existing container is removed
+    #[serde(skip)]
+    api_key: Option<String>,
+    proxy: Option<String>,
+    inherit_proxy: bool,
+    scratch: bool,
+    shared_tmp: bool,
+    no_default_mounts: bool,
+    no_default_folders: bool,
+    entrypoint: Option<String>,
+    match_user: bool,
+    group_add: Vec<String>,
+    isolated: bool,
+    seccomp: Option<PathBuf>,
+    security_opts: Vec<String>,
+    network: Option<String>,
+    restart_policy: Option<String>,
+    /// From `--ttl`: when `reap` may remove the container
+    #[serde(skip)]
+    expires_at: Option<chrono::DateTime<chrono::Local>>,
 }
 
-/// Named sessions registry - maps session names to conversation IDs
-#[derive(Serialize, Deserialize, Default)]
-struct SessionsRegistry {
-    sessions: HashMap<String, String>,
+/// A sandbox container as reported by `docker ps`
+#[derive(Clone)]
+struct SandboxSummary {
+    name: String,
+    status: String,
+    ports: String,
+    created_at: String,
+    running: bool,
+    /// Value of the `list --group-by` label, if requested and set
+    group: Option<String>,
+    /// Writable layer size in bytes, only queried for `list --sort size`
+    size: Option<u64>,
 }
 
-/// Folder registry - maps folder paths to container names
-#[derive(Serialize, Deserialize, Default)]
-struct FolderRegistry {
-    /// Maps canonical folder path(s) hash to container name
-    folders: HashMap<String, ContainerEntry>,
+impl SandboxSummary {
+    /// Parse docker's `CreatedAt` (e.g. "2024-01-15 10:30:00 +0100 CET")
+    fn created(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        let ts = self
+            .created_at
+            .split_whitespace()
+            .take(3)
+            .collect::<Vec<_>>()
+            .join(" ");
+        chrono::DateTime::parse_from_str(&ts, "%Y-%m-%d %H:%M:%S %z").ok()
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct ContainerEntry {
-    container_name: String,
-    folder_paths: Vec<String>,
-    created_at: String,
+/// Sort orders for `list --sort`
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum ListSort {
+    Name,
+    #[default]
+    Created,
+    Status,
+    Size,
 }
 
-/// Parse and normalize a port mapping string
-/// Supports: "8080", "8080:8080", "127.0.0.1:8080:8080"
-fn normalize_port_mapping(port: &str) -> Result<String> {
-    let parts: Vec<&str> = port.split(':').collect();
-    match parts.len() {
-        1 => {
-            // Just a port number, map to same port on host
-            let p: u16 = parts[0].parse().context("Invalid port number")?;
-            Ok(format!("{}:{}", p, p))
+impl ListSort {
+    /// Sort in this order (the natural direction of each key), reversed if asked
+    fn apply(self, sandboxes: &mut [SandboxSummary], reverse: bool) {
+        match self {
+            ListSort::Name => sandboxes.sort_by(|a, b| a.name.cmp(&b.name)),
+            ListSort::Created => sandboxes.sort_by(|a, b| {
+                b.created()
+                    .cmp(&a.created())
+                    .then_with(|| a.name.cmp(&b.name))
+            }),
+            ListSort::Status => sandboxes
+                .sort_by(|a, b| b.running.cmp(&a.running).then_with(|| a.name.cmp(&b.name))),
+            ListSort::Size => {
+                sandboxes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)))
+            }
         }
-        2 => {
-            // host:container format
-            let _host: u16 = parts[0].parse().context("Invalid host port")?;
-            let _container: u16 = parts[1].parse().context("Invalid container port")?;
-            Ok(port.to_string())
+        if reverse {
+            sandboxes.reverse();
         }
-        3 => {
-            // ip:host:container format
-            let _host: u16 = parts[1].parse().context("Invalid host port")?;
-            let _container: u16 = parts[2].parse().context("Invalid container port")?;
-            Ok(port.to_string())
+    }
+}
+
+/// Parse a size as printed by `docker ps -s`, e.g. "12.3MB (virtual 1.2GB)"
+fn parse_docker_size(size: &str) -> Option<u64> {
+    let first = size.split_whitespace().next()?;
+    let split = first.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, unit) = first.split_at(split);
+    let multiplier: f64 = match unit {
+        "B" => 1.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        _ => return None,
+    };
+    Some((number.parse::<f64>().ok()? * multiplier) as u64)
+}
+
+/// Selects which containers `docker ps` reports as sandboxes
+#[derive(Default)]
+struct SandboxSelector {
+    /// Only containers created from this image
+    image: Option<String>,
+    /// Only containers carrying all of these labels (`KEY` or `KEY=VALUE`)
+    labels: Vec<String>,
+}
+
+impl SandboxSelector {
+    /// Sets of `--filter` arguments for `docker ps`, whose results are merged
+    /// (docker ANDs the filters within one call). Containers created before
+    /// sandboxes were labelled are matched by the default image, as they
+    /// used to be.
+    fn docker_filter_sets(&self) -> Vec<Vec<String>> {
+        let mut sets = vec![vec![
+            "--filter".to_string(),
+            format!("label={SANDBOX_LABEL}"),
+        ]];
+        if self
+            .image
+            .as_deref()
+            .is_none_or(|image| image == IMAGE_NAME)
+        {
+            sets.push(vec![
+                "--filter".to_string(),
+                format!("ancestor={IMAGE_NAME}"),
+            ]);
         }
-        _ => bail!(
-            "Invalid port format: {}. Use PORT, HOST:CONTAINER, or IP:HOST:CONTAINER",
-            port
-        ),
+        for set in &mut sets {
+            if let Some(ref image) = self.image {
+                set.extend(["--filter".to_string(), format!("ancestor={image}")]);
+            }
+            for label in &self.labels {
+                set.extend(["--filter".to_string(), format!("label={label}")]);
+            }
+        }
+        sets
     }
 }
 
-fn get_config_dir() -> Result<PathBuf> {
-    if let Ok(p) = std::env::var("CLAUDE_SANDBOX_CONFIG") {
-        return Ok(PathBuf::from(p));
+/// Run `docker ps` with `args` (whose `--format` must start with the name)
+/// for each filter set of `selector`, returning the output lines with each
+/// container listed once
+async fn docker_ps_sandboxes(selector: &SandboxSelector, args: &[&str]) -> Result<Vec<String>> {
+    let mut seen = std::collections::HashSet::new();
+    let mut lines = Vec::new();
+    for filters in selector.docker_filter_sets() {
+        let output = Command::new("docker")
+            .arg("ps")
+            .args(args)
+            .args(&filters)
+            .traced()
+            .output()
+            .await?;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let name = line.split('\t').next().unwrap_or_default();
+            if !name.is_empty() && seen.insert(name.to_string()) {
+                lines.push(line.to_string());
+            }
+        }
     }
-    dirs::home_dir()
-        .map(|h| h.join(".claude-sandbox"))
-        .context("Could not determine home directory")
+    Ok(lines)
 }
 
-fn save_last_session(name: &str) -> Result<()> {
-    let config_dir = get_config_dir()?;
-    std::fs::create_dir_all(&config_dir)?;
-    let path = config_dir.join("last_session");
-    std::fs::write(&path, name)?;
+/// Check a `--label`/`--label-filter` value (`KEY=VALUE`, or just `KEY` when
+/// `allow_bare_key`)
+fn validate_label(flag: &str, label: &str, allow_bare_key: bool) -> Result<()> {
+    let key = match label.split_once('=') {
+        Some((key, _)) => key,
+        None if allow_bare_key => label,
+        None => bail!("Invalid {} '{}': expected KEY=VALUE", flag, label),
+    };
+    if key.trim().is_empty() {
+        bail!("Invalid {} '{}': the key cannot be empty", flag, label);
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || ".-_/".contains(c))
+    {
+        bail!(
+            "Invalid {} '{}': keys may only contain letters, digits, '.', '-', '_' and '/'",
+            flag,
+            label
+        );
+    }
     Ok(())
 }
 
-fn get_last_session() -> Result<String> {
-    let config_dir = get_config_dir()?;
-    let path = config_dir.join("last_session");
-    if path.exists() {
-        let name = std::fs::read_to_string(&path)?.trim().to_string();
-        if !name.is_empty() {
-            return Ok(name);
+/// Read `KEY=VALUE` lines from a `--label-file`, skipping blanks and `#` comments
+fn read_label_file(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read label file {}", path.display()))?;
+    let mut labels = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
+        validate_label("label", line, false)
+            .with_context(|| format!("{}:{}", path.display(), i + 1))?;
+        labels.push(line.to_string());
     }
-    Ok(DEFAULT_SESSION.to_string())
+    Ok(labels)
 }
 
-/// Sanitize a string for use in container names
-fn sanitize_name(s: &str) -> String {
-    s.chars()
-        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
-        .collect::<String>()
-        .to_lowercase()
+/// Filters accepted by `list`
+#[derive(Default)]
+struct ListFilter {
+    selector: SandboxSelector,
+    since: Option<chrono::Duration>,
+    before: Option<chrono::Duration>,
+    running: bool,
+    /// Only these containers (used by `--here`)
+    containers: Option<Vec<String>>,
 }
 
-/// Derive container name from folder paths
-fn derive_container_name(folders: &[PathBuf]) -> Result<String> {
-    let names: Vec<String> = folders
-        .iter()
-        .filter_map(|f| {
-            f.canonicalize()
-                .ok()
-                .and_then(|abs| abs.file_name().and_then(|n| n.to_str()).map(sanitize_name))
-        })
-        .filter(|n| !n.is_empty())
-        .collect();
-
-    if names.is_empty() {
-        bail!("Could not derive container name from folders");
+impl ListFilter {
+    fn is_active(&self) -> bool {
+        self.containers.is_some()
+            || self.selector.image.is_some()
+            || !self.selector.labels.is_empty()
+            || self.since.is_some()
+            || self.before.is_some()
+            || self.running
     }
 
-    // Combine names, truncate if too long (docker limit is 128 chars)
-    let combined = names.join("-");
-    let name = format!("{}-{}", CONTAINER_PREFIX, combined);
-
-    // Truncate if too long
-    if name.len() > 64 {
-        Ok(name[..64].to_string())
-    } else {
-        Ok(name)
+    /// Check a creation time against the --since/--before window
+    fn matches_created(&self, created: Option<chrono::DateTime<chrono::FixedOffset>>) -> bool {
+        if self.since.is_none() && self.before.is_none() {
+            return true;
+        }
+        let Some(created) = created else {
+            return false;
+        };
+        let age = chrono::Local::now().signed_duration_since(created);
+        self.since.is_none_or(|d| age <= d) && self.before.is_none_or(|d| age >= d)
     }
 }
 
-/// Get the folder registry path
-fn get_folder_registry_path() -> Result<PathBuf> {
-    let config_dir = get_config_dir()?;
-    Ok(config_dir.join("folder_registry.json"))
+/// Name of the per-project services file read by `up`
+const SERVICES_FILE: &str = "claude-sandbox.services.toml";
+/// Label tying service containers to the sandbox they were started for
+const STACK_LABEL: &str = "claude-sandbox.stack";
+
+/// Contents of `claude-sandbox.services.toml`
+#[derive(Deserialize)]
+struct ServicesFile {
+    #[serde(default, rename = "service")]
+    services: Vec<ServiceSpec>,
 }
 
-/// Load the folder registry
-fn load_folder_registry() -> Result<FolderRegistry> {
-    let path = get_folder_registry_path()?;
-    if path.exists() {
-        let content = std::fs::read_to_string(&path)?;
-        Ok(serde_json::from_str(&content).unwrap_or_default())
-    } else {
-        Ok(FolderRegistry::default())
-    }
+/// An additional container started alongside the sandbox by `up`
+#[derive(Deserialize)]
+struct ServiceSpec {
+    /// Hostname of the service on the stack network
+    name: String,
+    image: String,
+    #[serde(default)]
+    ports: Vec<String>,
+    #[serde(default)]
+    env: Vec<String>,
 }
 
-/// Save the folder registry
-fn save_folder_registry(registry: &FolderRegistry) -> Result<()> {
-    let path = get_folder_registry_path()?;
-    let config_dir = get_config_dir()?;
-    std::fs::create_dir_all(&config_dir)?;
-    let content = serde_json::to_string_pretty(registry)?;
-    std::fs::write(&path, content)?;
-    Ok(())
+/// Options for `list`
+#[derive(Default)]
+struct ListOptions {
+    filter: ListFilter,
+    format: Option<String>,
+    count: bool,
+    group_by: Option<String>,
+    sort: ListSort,
+    reverse: bool,
 }
 
-/// Create a key for the folder registry from folder paths
-fn folder_key(folders: &[PathBuf]) -> Result<String> {
-    let mut paths: Vec<String> = folders
-        .iter()
-        .filter_map(|f| f.canonicalize().ok())
-        .map(|p| p.to_string_lossy().to_string())
-        .collect();
-    paths.sort();
-    Ok(paths.join(":"))
+/// Substitute `{{.Field}}` placeholders (and `\t`/`\n` escapes) in a `list --format` template
+fn render_template(template: &str, fields: &[(&str, String)]) -> Result<String> {
+    let template = template.replace("\\t", "\t").replace("\\n", "\n");
+    let mut out = String::new();
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .with_context(|| format!("Unclosed placeholder in template: {}", template))?;
+        let key = rest[start + 2..start + end].trim();
+        let key = key.strip_prefix('.').unwrap_or(key);
+        let value = fields
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+            .with_context(|| format!("Unknown template field '{}'", key))?;
+        out.push_str(value);
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
 }
 
-/// Register a container with its folders
-fn register_container(container_name: &str, folders: &[PathBuf]) -> Result<()> {
-    let mut registry = load_folder_registry()?;
-    let key = folder_key(folders)?;
-    let paths: Vec<String> = folders
-        .iter()
-        .filter_map(|f| f.canonicalize().ok())
-        .map(|p| p.to_string_lossy().to_string())
-        .collect();
+/// Options for `build_image`
+#[derive(Default)]
+struct BuildOptions {
+    tag: Option<String>,
+    edit: bool,
+    proxy: Option<String>,
+    inherit_proxy: bool,
+    prune_images: bool,
+    retry: u32,
+    build_args: Vec<String>,
+    no_cache: bool,
+    quiet: bool,
+    json: bool,
+}
 
-    registry.folders.insert(
-        key,
-        ContainerEntry {
-            container_name: container_name.to_string(),
-            folder_paths: paths,
-            created_at: chrono::Local::now().to_rfc3339(),
-        },
-    );
-    save_folder_registry(&registry)?;
-    Ok(())
+/// User settings read from `config.toml` in the config directory
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Settings {
+    /// Maximum number of running sandbox containers (overridden by --max-containers)
+    max_containers: Option<usize>,
+    /// Serialization format for the folder and session registries
+    registry_format: RegistryFormat,
+    /// Default command to run after a container is created (overridden by --post-start)
+    post_start: Option<String>,
+    /// Where claude-code keeps conversation history inside the container
+    history_path: Option<String>,
+    /// Folders mounted into every container (read-only unless suffixed with ":rw")
+    default_mounts: Vec<String>,
+    /// Docker context to use (overridden by --context)
+    docker_context: Option<String>,
+    /// Detach key sequence for interactive sessions (overridden by --detach-keys)
+    detach_keys: Option<String>,
 }
 
-/// Look up container name by folder path
-fn lookup_container_by_folder(folder: &str) -> Result<Option<String>> {
-    let registry = load_folder_registry()?;
-
-    // First, try exact path match
-    let folder_path = PathBuf::from(folder);
-    if let Ok(canonical) = folder_path.canonicalize() {
-        let canonical_str = canonical.to_string_lossy().to_string();
+/// Machine-wide defaults for `run`, read from `defaults.toml` in the config
+/// directory. They have the lowest precedence: command-line flags win, and so
+/// do `max_containers`/`post_start` from `config.toml`.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RunDefaults {
+    image: Option<String>,
+    memory: Option<String>,
+    memory_swap: Option<String>,
+    memory_reservation: Option<String>,
+    cpus: Option<String>,
+    cpuset_cpus: Option<String>,
+    pids_limit: Option<u64>,
+    ports: Vec<String>,
+    add_hosts: Vec<String>,
+    env: Vec<String>,
+    labels: Vec<String>,
+    proxy: Option<String>,
+    inherit_proxy: bool,
+    shared_tmp: bool,
+    entrypoint: Option<String>,
+    restart: Option<String>,
+    match_user: bool,
+    group_add: Vec<String>,
+    isolated: bool,
+    security_opts: Vec<String>,
+    dangerously_skip_permissions: bool,
+    max_containers: Option<usize>,
+    post_start: Option<String>,
+}
 
-        // Check if this folder is part of any registered container
-        for entry in registry.folders.values() {
-            if entry.folder_paths.contains(&canonical_str) {
-                return Ok(Some(entry.container_name.clone()));
-            }
-            // Also check if folder name matches container suffix
-            if let Some(name) = canonical.file_name().and_then(|n| n.to_str()) {
-                let expected = format!("{}-{}", CONTAINER_PREFIX, sanitize_name(name));
-                if entry.container_name == expected || entry.container_name.starts_with(&expected) {
-                    return Ok(Some(entry.container_name.clone()));
-                }
+impl RunDefaults {
+    /// Fill in everything the command line left unset. List options are
+    /// only taken from the defaults when none were given on the command line.
+    fn apply(self, config: &mut RunConfig, settings: &Settings) {
+        let c = &mut config.container;
+        c.image = c.image.take().or(self.image);
+        c.memory = c.memory.take().or(self.memory);
+        c.memory_swap = c.memory_swap.take().or(self.memory_swap);
+        c.memory_reservation = c.memory_reservation.take().or(self.memory_reservation);
+        c.cpus = c.cpus.take().or(self.cpus);
+        c.cpuset_cpus = c.cpuset_cpus.take().or(self.cpuset_cpus);
+        c.pids_limit = c.pids_limit.or(self.pids_limit);
+        c.proxy = c.proxy.take().or(self.proxy);
+        c.entrypoint = c.entrypoint.take().or(self.entrypoint);
+        c.restart_policy = c.restart_policy.take().or(self.restart);
+        c.inherit_proxy |= self.inherit_proxy;
+        c.shared_tmp |= self.shared_tmp;
+        c.match_user |= self.match_user;
+        c.isolated |= self.isolated;
+        for (current, default) in [
+            (&mut c.ports, self.ports),
+            (&mut c.add_hosts, self.add_hosts),
+            (&mut c.env_vars, self.env),
+            (&mut c.labels, self.labels),
+            (&mut c.group_add, self.group_add),
+            (&mut c.security_opts, self.security_opts),
+        ] {
+            if current.is_empty() {
+                *current = default;
             }
         }
+        config.dangerously_skip_permissions |= self.dangerously_skip_permissions;
+        config.max_containers = config
+            .max_containers
+            .or(settings.max_containers)
+            .or(self.max_containers);
+        config.post_start = config
+            .post_start
+            .take()
+            .or_else(|| settings.post_start.clone())
+            .or(self.post_start);
     }
-
-    Ok(None)
 }
 
-/// Resolve target (folder path or container name) to container name
-fn resolve_target_to_container(target: Option<&str>) -> Result<String> {
-    match target {
-        None => get_last_session(),
-        Some(t) => {
-            // Check if it's an existing container name
-            let path = PathBuf::from(t);
-            if path.exists() && path.is_dir() {
-                // It's a folder path, look up or derive container name
-                if let Some(name) = lookup_container_by_folder(t)? {
-                    return Ok(name);
-                }
-                // Not registered, derive from folder name
-                return derive_container_name(&[path]);
-            }
+fn load_run_defaults() -> Result<RunDefaults> {
+    let path = get_config_dir()?.join("defaults.toml");
+    if !path.exists() {
+        return Ok(RunDefaults::default());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    toml::from_str(&content).with_context(|| format!("Invalid defaults file: {}", path.display()))
+}
 
-            // Check if it looks like a container name (starts with prefix)
-            if t.starts_with(CONTAINER_PREFIX) {
-                return Ok(t.to_string());
-            }
+/// On-disk format of the registry files
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum RegistryFormat {
+    #[default]
+    Json,
+    Yaml,
+}
 
-            // Try as folder name without path
-            if let Some(name) = lookup_container_by_folder(t)? {
-                return Ok(name);
-            }
+impl RegistryFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RegistryFormat::Json => "json",
+            RegistryFormat::Yaml => "yaml",
+        }
+    }
 
-            // Assume it's a container name
-            Ok(t.to_string())
+    fn other(self) -> RegistryFormat {
+        match self {
+            RegistryFormat::Json => RegistryFormat::Yaml,
+            RegistryFormat::Yaml => RegistryFormat::Json,
         }
     }
+
+    fn parse<T: serde::de::DeserializeOwned>(self, content: &str) -> Result<T> {
+        Ok(match self {
+            RegistryFormat::Json => serde_json::from_str(content)?,
+            RegistryFormat::Yaml => serde_yaml_ng::from_str(content)?,
+        })
+    }
+
+    fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            RegistryFormat::Json => serde_json::to_string_pretty(value)?,
+            RegistryFormat::Yaml => serde_yaml_ng::to_string(value)?,
+        })
+    }
 }
 
-/// Get per-container config directory for isolated state
-fn get_container_config_dir(container_name: &str) -> Result<PathBuf> {
-    let config_dir = get_config_dir()?;
-    Ok(config_dir.join("containers").join(container_name))
+/// Named sessions registry - maps session names to conversation IDs
+#[derive(Serialize, Deserialize, Default)]
+struct SessionsRegistry {
+    sessions: HashMap<String, NamedSession>,
 }
 
-fn get_sessions_registry_path() -> Result<PathBuf> {
-    let config_dir = get_config_dir()?;
-    Ok(config_dir.join("named_sessions.json"))
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(from = "NamedSessionRecord")]
+struct NamedSession {
+    conversation_id: String,
+    /// Initial prompt the session was started with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt: Option<String>,
 }
 
-fn load_sessions_registry() -> Result<SessionsRegistry> {
-    let path = get_sessions_registry_path()?;
-    if path.exists() {
-        let content = std::fs::read_to_string(&path)?;
-        Ok(serde_json::from_str(&content).unwrap_or_default())
-    } else {
-        Ok(SessionsRegistry::default())
+/// On-disk form of a named session; older registries stored only the conversation ID
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NamedSessionRecord {
+    Legacy(String),
+    Current {
+        conversation_id: String,
+        #[serde(default)]
+        prompt: Option<String>,
+    },
+}
+
+impl From<NamedSessionRecord> for NamedSession {
+    fn from(record: NamedSessionRecord) -> Self {
+        match record {
+            NamedSessionRecord::Legacy(conversation_id) => NamedSession {
+                conversation_id,
+                prompt: None,
+            },
+            NamedSessionRecord::Current {
+                conversation_id,
+                prompt,
+            } => NamedSession {
+                conversation_id,
+                prompt,
+            },
+        }
     }
 }
 
-fn save_sessions_registry(registry: &SessionsRegistry) -> Result<()> {
-    let path = get_sessions_registry_path()?;
-    let config_dir = get_config_dir()?;
-    std::fs::create_dir_all(&config_dir)?;
-    let content = serde_json::to_string_pretty(registry)?;
-    std::fs::write(&path, content)?;
-    Ok(())
+impl NamedSession {
+    /// Short title derived from the first line of the initial prompt
+    fn title(&self) -> Option<String> {
+        let line = self
+            .prompt
+            .as_deref()?
+            .lines()
+            .find(|l| !l.trim().is_empty())?
+            .trim();
+        if line.chars().count() > 60 {
+            Some(format!("{}…", line.chars().take(59).collect::<String>()))
+        } else {
+            Some(line.to_string())
+        }
+    }
 }
 
-fn save_named_session(name: &str, conversation_id: &str) -> Result<()> {
-    let mut registry = load_sessions_registry()?;
-    registry
-        .sessions
-        .insert(name.to_string(), conversation_id.to_string());
-    save_sessions_registry(&registry)?;
-    Ok(())
+/// Folder registry - maps folder paths to container names
+#[derive(Serialize, Deserialize, Default)]
+struct FolderRegistry {
+    /// Maps canonical folder path(s) hash to container name
+    folders: HashMap<String, ContainerEntry>,
 }
 
-fn get_named_session(name: &str) -> Result<Option<String>> {
-    let registry = load_sessions_registry()?;
-    Ok(registry.sessions.get(name).cloned())
+#[derive(Serialize, Deserialize, Clone)]
+struct ContainerEntry {
+    container_name: String,
+    folder_paths: Vec<String>,
+    created_at: String,
+    #[serde(default)]
+    alias: Option<String>,
+    /// `-e KEY=VALUE` variables, reapplied whenever the container is recreated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    env_vars: Vec<String>,
+    /// The user confirmed running this container with --dangerously-skip-permissions
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    dangerous_acknowledged: bool,
+    /// Expiry time (RFC 3339) from `run --ttl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    /// Free-text note from `run --note` / `note`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    /// `docker run` options the container was created with (missing for
+    /// containers registered by older versions)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    options: Option<ContainerOptions>,
 }
 
-/// Detect the most recent conversation ID by inspecting the projects directory.
-/// Claude Code stores conversations as `<uuid>.jsonl` files under
-/// `/home/claude/.claude/projects/<encoded-path>/`. We pick the most recently
-/// modified file and return its basename without the `.jsonl` suffix.
-async fn detect_latest_conversation_id(container: &str) -> Result<Option<String>> {
-    let output = Command::new("docker")
-        .args([
-            "exec", container, "bash", "-c",
-            "find /home/claude/.claude/projects -name '*.jsonl' -type f -printf '%T@ %p\\n' 2>/dev/null | sort -rn | head -1 | awk '{print $2}' | xargs -I{} basename {} .jsonl"
-        ])
-        .output()
-        .await?;
+/// Version of the JSON emitted on stdout and in JSON logs. Bump it only for
+/// incompatible changes (removed or retyped fields), not for additions.
+const SCHEMA_VERSION: u32 = 1;
 
-    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    if id.is_empty() {
-        Ok(None)
-    } else {
-        Ok(Some(id))
+/// Adds a top-level `schema_version` to a JSON object payload
+#[derive(Serialize)]
+struct Versioned<T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    payload: T,
+}
+
+impl<T: Serialize> Versioned<T> {
+    fn new(payload: T) -> Self {
+        Versioned {
+            schema_version: SCHEMA_VERSION,
+            payload,
+        }
     }
 }
 
-/// Seed the (bind-mounted) host `.claude` directory with defaults baked into
-/// the image. The main container mounts the host directory over
-/// `/home/claude/.claude`, which shadows the image's pre-installed plugins;
-/// this one-shot copy populates the host directory before that mount takes
-/// effect. `cp -rn` (no-clobber) ensures existing user state is never
-/// overwritten on subsequent runs.
-async fn seed_image_defaults(global_claude_dir: &std::path::Path) -> Result<()> {
-    // Skip if the host dir already contains a plugins/ subdirectory.
-    if global_claude_dir.join("plugins").exists() {
-        return Ok(());
+/// Compact single-line JSON, as used for JSON-lines output
+impl<T: Serialize> std::fmt::Display for Versioned<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+        f.write_str(&json)
     }
+}
 
-    println!(
-        "{}",
-        "Seeding default plugins from image into host state dir (first run)...".cyan()
-    );
+/// One line of a container's `history.jsonl` audit log
+#[derive(Serialize, Deserialize)]
+struct HistoryEntry {
+    timestamp: String,
+    action: String,
+    #[serde(default)]
+    prompt: Option<String>,
+    #[serde(default)]
+    session_name: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+}
 
-    let mount_spec = format!("{}:/seed-target", global_claude_dir.display());
-    let output = Command::new("docker")
-        .args([
-            "run",
-            "--rm",
-            "-v",
-            &mount_spec,
-            IMAGE_NAME,
-            "bash",
-            "-c",
-            "cp -rn /home/claude/.claude/. /seed-target/",
-        ])
-        .output()
-        .await?;
+/// Parse a duration like "30s", "15m", "2h", "7d" or "1w"
+fn parse_duration(s: &str) -> Result<chrono::Duration> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .with_context(|| format!("Missing unit in duration '{}' (use s, m, h, d or w)", s))?;
+    let (num, unit) = s.split_at(split);
+    let n: i64 = num
+        .parse()
+        .with_context(|| format!("Invalid duration: {}", s))?;
+    let duration = match unit {
+        "s" => chrono::Duration::try_seconds(n),
+        "m" => chrono::Duration::try_minutes(n),
+        "h" => chrono::Duration::try_hours(n),
+        "d" => chrono::Duration::try_days(n),
+        "w" => chrono::Duration::try_weeks(n),
+        _ => bail!("Invalid duration unit '{}' (use s, m, h, d or w)", unit),
+    };
+    duration.with_context(|| format!("Duration '{}' is too large", s))
+}
+
+/// Proxy variables for the container or build. An explicit `proxy` wins over
+/// the host's variables (used with `inherit`). A proxy on localhost is
+/// rewritten to `host.docker.internal` so it is reachable from the container;
+/// the returned flag says whether that host mapping is needed.
+fn proxy_env(proxy: Option<&str>, inherit: bool) -> (Vec<(String, String)>, bool) {
+    let host_var = |name: &str| {
+        std::env::var(name)
+            .or_else(|_| std::env::var(name.to_lowercase()))
+            .ok()
+            .filter(|v| !v.is_empty())
+    };
+    let (http, https) = match proxy {
+        Some(p) => (Some(p.to_string()), Some(p.to_string())),
+        None if inherit => (host_var("HTTP_PROXY"), host_var("HTTPS_PROXY")),
+        None => (None, None),
+    };
+    if http.is_none() && https.is_none() {
+        return (Vec::new(), false);
+    }
+    let no_proxy = inherit
+        .then(|| host_var("NO_PROXY"))
+        .flatten()
+        .unwrap_or_else(|| "localhost,127.0.0.1".to_string());
+
+    let mut needs_host_gateway = false;
+    let mut forward = |url: String| {
+        let mut rewritten = url.clone();
+        for local in ["localhost", "127.0.0.1"] {
+            rewritten = rewritten.replacen(&format!("//{local}"), "//host.docker.internal", 1);
+        }
+        needs_host_gateway |= rewritten != url;
+        rewritten
+    };
+    let mut vars = Vec::new();
+    for (name, value) in [
+        ("HTTP_PROXY", http.map(&mut forward)),
+        ("HTTPS_PROXY", https.map(&mut forward)),
+    ] {
+        if let Some(value) = value {
+            vars.push((name.to_lowercase(), value.clone()));
+            vars.push((name.to_string(), value));
+        }
+    }
+    vars.push(("no_proxy".to_string(), no_proxy.clone()));
+    vars.push(("NO_PROXY".to_string(), no_proxy));
+    (vars, needs_host_gateway)
+}
+
+/// Parse a `KEY=@path` secret spec and read the value from the file,
+/// trimming a single trailing newline
+fn read_env_secret(spec: &str) -> Result<(String, String)> {
+    let (key, path) = spec
+        .split_once("=@")
+        .with_context(|| format!("Invalid --env-secret '{}'. Use KEY=@path", spec))?;
+    if key.is_empty() {
+        bail!("Invalid --env-secret '{}': empty variable name", spec);
+    }
+    let mut value = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read secret file for {}: {}", key, path))?;
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    Ok((key.to_string(), value))
+}
+
+/// Read the API key from `--api-key-file` or `--api-key-keychain`, if given.
+/// Without either, ANTHROPIC_API_KEY is taken from the host environment.
+fn read_api_key(options: &ContainerOptions) -> Result<Option<String>> {
+    let key = if let Some(ref path) = options.api_key_file {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read API key file: {}", path.display()))?
+    } else if let Some(ref name) = options.api_key_keychain {
+        read_keychain_password(name)?
+    } else {
+        return Ok(None);
+    };
+    let key = key.trim();
+    if key.is_empty() {
+        bail!("The API key source is empty");
+    }
+    Ok(Some(key.to_string()))
+}
 
+#[cfg(target_os = "macos")]
+fn read_keychain_password(name: &str) -> Result<String> {
+    let output = std::process::Command::new("security")
+        .args(["find-generic-password", "-s", name, "-w"])
+        .output()
+        .context("Failed to run 'security'")?;
     if !output.status.success() {
-        eprintln!(
-            "{} Seed step failed (continuing): {}",
-            "⚠".yellow(),
+        bail!(
+            "No Keychain item '{}' found: {}",
+            name,
             String::from_utf8_lossy(&output.stderr).trim()
         );
     }
-    Ok(())
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum SessionAction {
-    NewSession,
-    Continue,
+#[cfg(not(target_os = "macos"))]
+fn read_keychain_password(_name: &str) -> Result<String> {
+    bail!("--api-key-keychain is only supported on macOS; use --api-key-file instead")
 }
 
-fn get_dockerfile_content() -> String {
-    let template = r#"# syntax=docker/dockerfile:1.7
-FROM debian:bookworm-slim
+/// Split an `--entrypoint` command into words the way a POSIX shell would,
+/// so quoted arguments stay together
+fn split_entrypoint(command: &str) -> Result<Vec<String>> {
+    let words = shell_words::split(command)
+        .with_context(|| format!("Invalid --entrypoint '{}'", command))?;
+    if words.is_empty() {
+        bail!("--entrypoint cannot be empty");
+    }
+    Ok(words)
+}
 
-ENV HOME=/home/claude
-ENV LANG=C.UTF-8
-ENV LC_ALL=C.UTF-8
-ENV TERM=xterm-256color
-ENV DEBIAN_FRONTEND=noninteractive
+/// Parse a docker memory size such as "512m", "4gb", "1.5g" or "1073741824"
+/// into bytes: a number (optionally fractional) followed by an optional
+/// k/m/g unit and an optional "b", case-insensitive, as `docker run --memory` accepts
+fn parse_memory_limit(value: &str) -> Option<u64> {
+    let lower = value.to_ascii_lowercase();
+    let rest = lower.strip_suffix('b').unwrap_or(&lower);
+    let (number, multiplier) = match rest.chars().last()? {
+        'k' => (&rest[..rest.len() - 1], 1u64 << 10),
+        'm' => (&rest[..rest.len() - 1], 1 << 20),
+        'g' => (&rest[..rest.len() - 1], 1 << 30),
+        _ => (rest, 1),
+    };
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    if !digits(whole) || (number.contains('.') && !digits(fraction)) {
+        return None;
+    }
+    Some((number.parse::<f64>().ok()? * multiplier as f64) as u64)
+}
 
-# System dependencies (with BuildKit apt cache mounts for fast rebuilds).
-# Includes search/QoL tools Claude uses constantly: ripgrep, fd, tree, bat, vim, less, jq.
-RUN --mount=type=cache,target=/var/cache/apt,sharing=locked \
-    --mount=type=cache,target=/var/lib/apt/lists,sharing=locked \
-    rm -f /etc/apt/apt.conf.d/docker-clean && \
-    apt-get update && apt-get install -y --no-install-recommends \
-    curl \
-    wget \
-    git \
-    ca-certificates \
-    sudo \
-    gnupg \
-    build-essential \
-    pkg-config \
-    libssl-dev \
-    xz-utils \
-    jq \
-    ripgrep \
-    fd-find \
-    tree \
-    bat \
-    less \
-    vim \
-    htop \
-    python3 \
-    python3-pip \
-    python3-venv \
-    python3-numpy \
-    python3-pandas \
-    python3-requests \
-    && ln -sf /usr/bin/fdfind /usr/local/bin/fd \
-    && ln -sf /usr/bin/batcat /usr/local/bin/bat
+/// Validate a docker memory size such as "512m", "4gb" or "1.5g"
+fn validate_memory_limit(flag: &str, value: &str) -> Result<()> {
+    if parse_memory_limit(value).is_none() {
+        bail!(
+            "Invalid {} '{}'. Use a number with an optional unit (b, k, m, g), e.g. \"4g\" or \"512mb\"",
+            flag,
+            value
+        );
+    }
+    Ok(())
+}
 
-# GitHub CLI (gh) from official repo
-RUN --mount=type=cache,target=/var/cache/apt,sharing=locked \
-    --mount=type=cache,target=/var/lib/apt/lists,sharing=locked \
-    mkdir -p -m 755 /etc/apt/keyrings && \
-    curl -fsSL https://cli.github.com/packages/githubcli-archive-keyring.gpg \
-        -o /etc/apt/keyrings/githubcli-archive-keyring.gpg && \
-    chmod go+r /etc/apt/keyrings/githubcli-archive-keyring.gpg && \
-    echo "deb [arch=$(dpkg --print-architecture) signed-by=/etc/apt/keyrings/githubcli-archive-keyring.gpg] https://cli.github.com/packages stable main" \
-        > /etc/apt/sources.list.d/github-cli.list && \
-    apt-get update && apt-get install -y --no-install-recommends gh
+/// Size in bytes of a memory limit already checked by `validate_memory_limit`
+fn memory_limit_bytes(value: &str) -> u64 {
+    parse_memory_limit(value).unwrap_or(0)
+}
 
-# Create user and directories. Grant passwordless sudo so Claude can install
-# additional tools at runtime without leaving the container.
-RUN useradd -m -s /bin/bash -d /home/claude claude && \
-    mkdir -p /home/claude/workspace /home/claude/.claude /home/claude/.config /home/claude/.local/bin && \
-    echo 'claude ALL=(ALL) NOPASSWD:ALL' > /etc/sudoers.d/claude && \
-    chmod 440 /etc/sudoers.d/claude
+/// Validate an `--add-host` mapping of the form `NAME:IP`
+fn validate_add_host(mapping: &str) -> Result<()> {
+    let (name, ip) = mapping
+        .split_once(':')
+        .with_context(|| format!("Invalid --add-host '{}'. Use NAME:IP", mapping))?;
+    let name_ok = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+    if !name_ok {
+        bail!("Invalid host name in --add-host '{}'", mapping);
+    }
+    if ip != "host-gateway" && ip.parse::<std::net::IpAddr>().is_err() {
+        bail!("Invalid IP address in --add-host '{}'", mapping);
+    }
+    Ok(())
+}
 
-# Default Claude config: preinstall context7 MCP server and pre-allow common
-# read-only commands so Claude doesn't prompt for every routine call.
-RUN cat > /home/claude/.claude.json <<'JSON'
-{
-  "mcpServers": {
-    "context7": {
-      "command": "npx",
-      "args": ["-y", "@upstash/context7-mcp@latest"]
+/// Validate a `--build-arg` of the form `KEY=VALUE`
+fn validate_build_arg(arg: &str) -> Result<()> {
+    let (key, _) = arg
+        .split_once('=')
+        .with_context(|| format!("Invalid --build-arg '{}'. Use KEY=VALUE", arg))?;
+    let key_ok = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !key_ok {
+        bail!(
+            "Invalid --build-arg '{}': the name may only contain letters, digits and '_'",
+            arg
+        );
     }
-  }
+    Ok(())
 }
-JSON
 
-RUN cat > /home/claude/.claude/settings.json <<'JSON'
-{
-  "permissions": {
-    "allow": [
-      "Bash(ls:*)",
-      "Bash(cat:*)",
-      "Bash(pwd)",
-      "Bash(echo:*)",
-      "Bash(rg:*)",
-      "Bash(fd:*)",
-      "Bash(tree:*)",
-      "Bash(bat:*)",
-      "Bash(jq:*)",
-      "Bash(wc:*)",
-      "Bash(head:*)",
-      "Bash(tail:*)",
-      "Bash(file:*)",
-      "Bash(which:*)",
-      "Bash(env)",
-      "Bash(git status:*)",
-      "Bash(git diff:*)",
-      "Bash(git log:*)",
-      "Bash(git branch:*)",
-      "Bash(git show:*)",
-      "Bash(git remote:*)",
-      "Bash(gh pr view:*)",
-      "Bash(gh pr list:*)",
-      "Bash(gh issue view:*)",
-      "Bash(gh issue list:*)",
-      "Bash(gh repo view:*)",
-      "Bash(python3 --version)",
-      "Bash(node --version)",
-      "Bash(npm --version)",
-      "Bash(cargo --version)",
-      "Bash(rustc --version)",
-      "Bash(forge --version)"
-    ]
-  }
+/// Validate a docker restart policy (`no`, `on-failure[:N]`, `unless-stopped`, `always`)
+fn validate_restart_policy(policy: &str) -> Result<()> {
+    let valid = match policy.split_once(':') {
+        Some(("on-failure", retries)) => retries.parse::<u32>().is_ok(),
+        Some(_) => false,
+        None => matches!(policy, "no" | "on-failure" | "unless-stopped" | "always"),
+    };
+    if !valid {
+        bail!(
+            "Invalid restart policy '{}'. Use no, on-failure[:N], unless-stopped or always",
+            policy
+        );
+    }
+    Ok(())
 }
-JSON
-
-RUN echo '{}' > /home/claude/.claude.json.backup && \
-    chown -R claude:claude /home/claude
 
-USER claude
-WORKDIR /home/claude
+/// Substitute `{{KEY}}` placeholders in a prompt template from `KEY=VALUE` vars.
+/// Fails on placeholders without a value unless `allow_unfilled` is set.
+fn fill_prompt_template(template: &str, vars: &[String], allow_unfilled: bool) -> Result<String> {
+    let vars: HashMap<&str, &str> = vars
+        .iter()
+        .map(|v| {
+            v.split_once('=')
+                .with_context(|| format!("Invalid --var '{}'. Use KEY=VALUE", v))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut out = String::new();
+    let mut unfilled = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + len + 2];
+        let key = placeholder[2..placeholder.len() - 2].trim();
+        out.push_str(&rest[..start]);
+        match vars.get(key) {
+            Some(value) => out.push_str(value),
+            None => {
+                unfilled.push(key.to_string());
+                out.push_str(placeholder);
+            }
+        }
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
 
-# Set up PATH for all tools
-ENV PATH="/home/claude/.local/bin:/home/claude/.cargo/bin:/home/claude/.foundry/bin:${PATH}"
-ENV RUSTUP_HOME=/home/claude/.rustup
-ENV CARGO_HOME=/home/claude/.cargo
-ENV NVM_DIR=/home/claude/.nvm
+    if !unfilled.is_empty() && !allow_unfilled {
+        unfilled.sort();
+        unfilled.dedup();
+        bail!(
+            "Unfilled template placeholders: {}. Provide them with --var KEY=VALUE",
+            unfilled.join(", ")
+        );
+    }
+    Ok(out)
+}
 
-# Install rustup and Rust (stable toolchain)
-RUN curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y --default-toolchain stable
+/// Parse and normalize a port mapping string
+/// Supports: "8080", "8080:8080", "127.0.0.1:8080:8080"
+fn normalize_port_mapping(port: &str) -> Result<String> {
+    let parts: Vec<&str> = port.split(':').collect();
+    match parts.len() {
+        1 => {
+            // Just a port number, map to same port on host
+            let p: u16 = parts[0].parse().context("Invalid port number")?;
+            Ok(format!("{}:{}", p, p))
+        }
+        2 => {
+            // host:container format
+            let _host: u16 = parts[0].parse().context("Invalid host port")?;
+            let _container: u16 = parts[1].parse().context("Invalid container port")?;
+            Ok(port.to_string())
+        }
+        3 => {
+            // ip:host:container format
+            let _host: u16 = parts[1].parse().context("Invalid host port")?;
+            let _container: u16 = parts[2].parse().context("Invalid container port")?;
+            Ok(port.to_string())
+        }
+        _ => bail!(
+            "Invalid port format: {}. Use PORT, HOST:CONTAINER, or IP:HOST:CONTAINER",
+            port
+        ),
+    }
+}
 
-# Install Foundry (forge, cast, anvil)
-RUN curl -L https://foundry.paradigm.xyz | bash && \
-    /home/claude/.foundry/bin/foundryup
+/// Expand port ranges into individual mappings: "8000-8002" becomes
+/// 8000:8000, 8001:8001, 8002:8002 and "8000-8001:9000-9001" pairs the host
+/// and container ranges up. Specs without a range are returned unchanged.
+fn expand_port_ranges(ports: &[String]) -> Result<Vec<String>> {
+    let range = |spec: &str, what: &str| -> Result<(u16, u16)> {
+        let (start, end) = spec.split_once('-').unwrap_or((spec, spec));
+        let start: u16 = start
+            .parse()
+            .with_context(|| format!("Invalid {} '{}'", what, spec))?;
+        let end: u16 = end
+            .parse()
+            .with_context(|| format!("Invalid {} '{}'", what, spec))?;
+        if start > end {
+            bail!("Invalid {} '{}': start is after end", what, spec);
+        }
+        Ok((start, end))
+    };
 
-# Install nvm and latest Node.js (npm cache mount speeds up reinstalls)
-RUN --mount=type=cache,target=/home/claude/.npm,uid=1000,gid=1000 \
-    curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.40.1/install.sh | bash && \
-    bash -c "source $NVM_DIR/nvm.sh && nvm install node"
+    let mut expanded = Vec::new();
+    for port in ports {
+        if !port.contains('-') {
+            expanded.push(port.clone());
+            continue;
+        }
+        let parts: Vec<&str> = port.split(':').collect();
+        let (ip, host, container) = match parts.as_slice() {
+            [p] => (None, *p, *p),
+            [h, c] => (None, *h, *c),
+            [ip, h, c] => (Some(*ip), *h, *c),
+            _ => bail!(
+                "Invalid port format: {}. Use PORT, HOST:CONTAINER, or IP:HOST:CONTAINER",
+                port
+            ),
+        };
+        let host = range(host, "host port range")?;
+        let container = range(container, "container port range")?;
+        if host.1 - host.0 != container.1 - container.0 {
+            bail!(
+                "Port range '{}' maps {} host port(s) to {} container port(s)",
+                port,
+                host.1 - host.0 + 1,
+                container.1 - container.0 + 1
+            );
+        }
+        for (h, c) in (host.0..=host.1).zip(container.0..=container.1) {
+            expanded.push(match ip {
+                Some(ip) => format!("{}:{}:{}", ip, h, c),
+                None => format!("{}:{}", h, c),
+            });
+        }
+    }
+    Ok(expanded)
+}
 
-# Create symlinks for node/npm/npx to be accessible without sourcing nvm
-RUN bash -c "source $NVM_DIR/nvm.sh && \
-    ln -sf \$(which node) /home/claude/.local/bin/node && \
-    ln -sf \$(which npm) /home/claude/.local/bin/npm && \
-    ln -sf \$(which npx) /home/claude/.local/bin/npx"
+/// Config directory given via `--config-dir`, set once at startup
+static CONFIG_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
 
-# Install claude-code via official installer
-RUN curl -fsSL https://claude.ai/install.sh | bash
+/// Set once at startup from `--verbose`
+static VERBOSE: OnceLock<bool> = OnceLock::new();
 
-# Pre-install the official Anthropic plugin marketplace and a curated plugin
-# set. These commands write to /home/claude/.claude/, which is later seeded
-# into the bind-mounted host state dir on first container start. Failures
-# (e.g. claude CLI requires auth) are logged but do not fail the build,
-# so the image is still usable without plugins.
-__PLUGIN_INSTALL_BLOCK__
+/// How confirmation prompts are answered
+#[derive(Clone, Copy, PartialEq)]
+enum PromptMode {
+    Ask,
+    /// `--yes`
+    AssumeYes,
+    /// `--no-interactive`: every prompt takes its default answer
+    NoInteractive,
+}
 
-# Setup bashrc for interactive shells
-RUN echo 'export PATH=\"/home/claude/.local/bin:/home/claude/.cargo/bin:/home/claude/.foundry/bin:\$PATH\"' >> /home/claude/.bashrc && \
-    echo 'export NVM_DIR=\"\$HOME/.nvm\"' >> /home/claude/.bashrc && \
-    echo '[ -s \"\$NVM_DIR/nvm.sh\" ] && . \"\$NVM_DIR/nvm.sh\"' >> /home/claude/.bashrc
+/// Set once at startup from `--yes`/`--no-interactive`
+static PROMPT_MODE: OnceLock<PromptMode> = OnceLock::new();
 
-# Verify all installations (fails the build if any tool is missing)
-RUN cargo --version && rustc --version && \
-    forge --version && \
-    node --version && npm --version && \
-    rg --version | head -1 && \
-    fd --version && \
-    bat --version | head -1 && \
-    tree --version | head -1 && \
-    jq --version && \
-    gh --version | head -1 && \
-    python3 --version && \
-    pip3 --version && \
-    sudo -n true
+fn prompt_mode() -> PromptMode {
+    PROMPT_MODE.get().copied().unwrap_or(PromptMode::Ask)
+}
 
-WORKDIR /home/claude/workspace
+/// Set once at startup from `--detach-keys` or `detach_keys` in config.toml
+static DETACH_KEYS: OnceLock<String> = OnceLock::new();
 
-CMD ["tail", "-f", "/dev/null"]
-"#;
+/// `--detach-keys` arguments for an interactive `docker exec`, if configured
+fn detach_keys_args() -> Vec<String> {
+    DETACH_KEYS
+        .get()
+        .map(|keys| vec!["--detach-keys".to_string(), keys.clone()])
+        .unwrap_or_default()
+}
 
-    let plugin_list = DEFAULT_PLUGINS.join(" ");
-    let plugin_block = format!(
-        "RUN (claude plugin marketplace add {source} && \\\n     \
-         for p in {list}; do \\\n         \
-         claude plugin install \"${{p}}@{name}\" || \\\n             \
-         echo \"WARN: plugin install failed: ${{p}}\"; \\\n     \
-         done) || echo \"WARN: plugin pre-install skipped (marketplace add failed)\"",
-        source = PLUGIN_MARKETPLACE_SOURCE,
-        list = plugin_list,
-        name = PLUGIN_MARKETPLACE_NAME,
-    );
-    template.replace("__PLUGIN_INSTALL_BLOCK__", &plugin_block)
+/// Validate a detach key sequence: comma-separated keys, each a single
+/// character or `ctrl-<key>` with key one of a-z, @, [, \, ], ^ or _
+fn validate_detach_keys(spec: &str) -> Result<()> {
+    for key in spec.split(',') {
+        let valid = match key.strip_prefix("ctrl-") {
+            Some(k) => {
+                k.len() == 1
+                    && k.chars()
+                        .all(|c| c.is_ascii_lowercase() || "@[\\]^_".contains(c))
+            }
+            None => key.len() == 1 && key.is_ascii(),
+        };
+        if !valid {
+            bail!(
+                "Invalid detach key '{}' in '{}'. Use comma-separated keys such as \
+                 \"ctrl-x,x\" (a single character or ctrl-<a-z, @, [, \\, ], ^, _>)",
+                key,
+                spec
+            );
+        }
+    }
+    Ok(())
 }
 
-/// Resolve a folder path to an absolute path and extract the folder name
-fn resolve_folder_path(folder: &PathBuf) -> Result<(PathBuf, String)> {
-    let abs = std::fs::canonicalize(folder)
-        .with_context(|| format!("Cannot access folder: {}", folder.display()))?;
+/// Quote a command-line word for display, hiding the values of
+/// secret-looking `KEY=VALUE` environment assignments
+fn display_arg(arg: &str) -> String {
+    let arg = redact_env(arg);
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=:/.,@%+".contains(c))
+    {
+        arg
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
 
-    let fname = abs
-        .file_name()
-        .map(|n| n.to_str().unwrap_or("project"))
-        .unwrap_or("project")
-        .to_string();
+/// Hide the value of a `KEY=VALUE` assignment whose key looks secret
+fn redact_env(assignment: &str) -> String {
+    const SECRET_MARKERS: &[&str] = &[
+        "KEY",
+        "TOKEN",
+        "SECRET",
+        "PASSWORD",
+        "PASSWD",
+        "CREDENTIAL",
+        "AUTH",
+    ];
+    match assignment.split_once('=') {
+        Some((key, _))
+            if !key.is_empty()
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && SECRET_MARKERS
+                    .iter()
+                    .any(|m| key.to_ascii_uppercase().contains(m)) =>
+        {
+            format!("{}=***", key)
+        }
+        _ => assignment.to_string(),
+    }
+}
 
-    Ok((abs, fname))
+/// Echo commands to stderr before they run when `--verbose` is given
+trait Traced {
+    fn traced(&mut self) -> &mut Self;
 }
 
-async fn check_docker() -> Result<()> {
-    let status = Command::new("docker")
-        .arg("info")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .await?;
-    if !status.success() {
-        bail!("Docker is not running. Please start Docker and try again.");
+impl Traced for std::process::Command {
+    fn traced(&mut self) -> &mut Self {
+        if VERBOSE.get().copied().unwrap_or(false) {
+            let words: Vec<String> = std::iter::once(self.get_program())
+                .chain(self.get_args())
+                .map(|a| display_arg(&a.to_string_lossy()))
+                .collect();
+            eprintln!("{} {}", "$".dimmed(), words.join(" ").dimmed());
+        }
+        self
     }
-    Ok(())
 }
 
-async fn image_exists() -> Result<bool> {
-    let output = Command::new("docker")
-        .args(["image", "inspect", IMAGE_NAME])
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .await?;
-    Ok(output.success())
+impl Traced for Command {
+    fn traced(&mut self) -> &mut Self {
+        self.as_std_mut().traced();
+        self
+    }
 }
 
-async fn container_exists(name: &str) -> Result<bool> {
-    let output = Command::new("docker")
-        .args(["container", "inspect", name])
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .await?;
-    Ok(output.success())
+fn get_config_dir() -> Result<PathBuf> {
+    if let Some(p) = CONFIG_DIR_OVERRIDE.get() {
+        return Ok(p.clone());
+    }
+    if let Ok(p) = std::env::var("CLAUDE_SANDBOX_CONFIG") {
+        return Ok(PathBuf::from(p));
+    }
+    dirs::home_dir()
+        .map(|h| h.join(".claude-sandbox"))
+        .context("Could not determine home directory")
 }
 
-async fn container_running(name: &str) -> Result<bool> {
-    let output = Command::new("docker")
-        .args(["inspect", "-f", "{{.State.Running}}", name])
-        .output()
-        .await?;
-    Ok(String::from_utf8_lossy(&output.stdout).trim() == "true")
+/// Load settings from `config.toml`, falling back to defaults if it doesn't exist
+fn load_settings() -> Result<Settings> {
+    let path = get_config_dir()?.join("config.toml");
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    toml::from_str(&content).with_context(|| format!("Invalid config file: {}", path.display()))
 }
 
-async fn build_image(no_cache: bool) -> Result<()> {
-    println!("{}", "Building Claude Code sandbox image...".cyan());
+fn save_last_session(name: &str) -> Result<()> {
     let config_dir = get_config_dir()?;
     std::fs::create_dir_all(&config_dir)?;
-    let dockerfile_path = config_dir.join("Dockerfile");
-    std::fs::write(&dockerfile_path, get_dockerfile_content())?;
-    let mut cmd = Command::new("docker");
-    // BuildKit is required for `# syntax=` and `RUN --mount=type=cache` in the Dockerfile.
-    cmd.env("DOCKER_BUILDKIT", "1");
-    cmd.args(["build", "-t", IMAGE_NAME]);
-    if no_cache {
-        cmd.arg("--no-cache");
-    }
-    cmd.args([
-        "-f",
-        dockerfile_path.to_str().unwrap(),
-        config_dir.to_str().unwrap(),
-    ]);
-    let status = cmd.status().await?;
-    if !status.success() {
-        bail!("Failed to build Docker image");
-    }
-    println!("{}", "Image built successfully!".green());
+    let path = config_dir.join("last_session");
+    std::fs::write(&path, name)?;
     Ok(())
 }
 
-async fn start_container(
-    name: &str,
-    folders: &[PathBuf],
-    memory: Option<&str>,
-    cpus: Option<&str>,
-    ports: &[String],
-    env_vars: &[String],
-) -> Result<()> {
-    // Per-container directory for isolated conversation history
-    let container_config_dir = get_container_config_dir(name)?;
-    std::fs::create_dir_all(&container_config_dir)?;
-
-    // Global config directory for shared settings (theme, preferences)
-    let global_config_dir = get_config_dir()?;
-    std::fs::create_dir_all(&global_config_dir)?;
+fn get_last_session() -> Result<String> {
+    let config_dir = get_config_dir()?;
+    let path = config_dir.join("last_session");
+    if path.exists() {
+        let name = std::fs::read_to_string(&path)?.trim().to_string();
+        if !name.is_empty() {
+            return Ok(name);
+        }
+    }
+    Ok(DEFAULT_SESSION.to_string())
+}
 
-    let mut args = vec![
-        "run".to_string(),
-        "-d".to_string(),
-        "--name".to_string(),
+/// Sanitize a string for use in container names
+fn sanitize_name(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Derive container name from folder paths
+fn derive_container_name(folders: &[PathBuf]) -> Result<String> {
+    let names: Vec<String> = folders
+        .iter()
+        .filter_map(|f| {
+            f.canonicalize()
+                .ok()
+                .and_then(|abs| abs.file_name().and_then(|n| n.to_str()).map(sanitize_name))
+        })
+        .filter(|n| !n.is_empty())
+        .collect();
+
+    if names.is_empty() {
+        bail!("Could not derive container name from folders");
+    }
+
+    // Combine names, truncate if too long (docker limit is 128 chars)
+    let combined = names.join("-");
+    let name = format!("{}-{}", CONTAINER_PREFIX, combined);
+
+    // Truncate if too long
+    if name.len() > 64 {
+        Ok(name[..64].to_string())
+    } else {
+        Ok(name)
+    }
+}
+
+/// Get the path of a registry file in the given format
+fn get_registry_path(stem: &str, format: RegistryFormat) -> Result<PathBuf> {
+    let config_dir = get_config_dir()?;
+    Ok(config_dir.join(format!("{}.{}", stem, format.extension())))
+}
+
+/// Load a registry in the configured format, falling back to the other
+/// format so an existing file is picked up (and migrated on the next save)
+fn load_registry<T: serde::de::DeserializeOwned + Default>(stem: &str) -> Result<T> {
+    let format = load_settings()?.registry_format;
+    for f in [format, format.other()] {
+        let path = get_registry_path(stem, f)?;
+        if path.exists() {
+            return read_registry_file(&path, f);
+        }
+    }
+    Ok(T::default())
+}
+
+/// Read and parse one registry file. A file that does not parse is an error
+/// rather than an empty registry, so a hand-editing typo is reported instead
+/// of being overwritten on the next save.
+fn read_registry_file<T: serde::de::DeserializeOwned>(
+    path: &Path,
+    format: RegistryFormat,
+) -> Result<T> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    format.parse(&content).with_context(|| {
+        format!(
+            "Invalid registry file {} (fix or remove it)",
+            path.display()
+        )
+    })
+}
+
+/// Save a registry in the configured format, removing a copy in the other format.
+/// The file is written to a temporary path and renamed into place so readers
+/// never see a partial write.
+fn save_registry<T: Serialize>(stem: &str, registry: &T) -> Result<()> {
+    let format = load_settings()?.registry_format;
+    let config_dir = get_config_dir()?;
+    std::fs::create_dir_all(&config_dir)?;
+    let content = format.serialize(registry)?;
+    let path = get_registry_path(stem, format)?;
+    let tmp = config_dir.join(format!(
+        ".{}.{}.{}.tmp",
+        stem,
+        format.extension(),
+        std::process::id()
+    ));
+    std::fs::write(&tmp, content)
+        .and_then(|_| std::fs::rename(&tmp, &path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&tmp);
+        })?;
+    let stale = get_registry_path(stem, format.other())?;
+    if stale.exists() {
+        std::fs::remove_file(&stale)?;
+    }
+    Ok(())
+}
+
+/// Take an exclusive advisory lock on a registry, blocking until other
+/// invocations release it. Hold the returned file across a load/modify/save
+/// sequence; the lock is released when it is dropped.
+fn lock_registry(stem: &str) -> Result<std::fs::File> {
+    use fs2::FileExt;
+    let config_dir = get_config_dir()?;
+    std::fs::create_dir_all(&config_dir)?;
+    let path = config_dir.join(format!("{}.lock", stem));
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.lock_exclusive()
+        .with_context(|| format!("Failed to lock {}", path.display()))?;
+    Ok(file)
+}
+
+/// Load the folder registry
+fn load_folder_registry() -> Result<FolderRegistry> {
+    load_registry("folder_registry")
+}
+
+/// Save the folder registry
+fn save_folder_registry(registry: &FolderRegistry) -> Result<()> {
+    save_registry("folder_registry", registry)
+}
+
+/// Create a key for the folder registry from folder paths
+fn folder_key(folders: &[PathBuf]) -> Result<String> {
+    let mut paths: Vec<String> = folders
+        .iter()
+        .filter_map(|f| f.canonicalize().ok())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    paths.sort();
+    Ok(paths.join(":"))
+}
+
+/// Register a container with its folders and the options it was created with
+fn register_container(
+    container_name: &str,
+    folders: &[PathBuf],
+    alias: Option<&str>,
+    options: &ContainerOptions,
+) -> Result<()> {
+    let env_vars = &options.env_vars;
+    let _lock = lock_registry("folder_registry")?;
+    let mut registry = load_folder_registry()?;
+    let key = folder_key(folders)?;
+    let paths: Vec<String> = folders
+        .iter()
+        .filter_map(|f| f.canonicalize().ok())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+
+    // Keep a previously assigned alias and env vars when recreating without
+    // --alias / -e
+    let previous = registry
+        .folders
+        .get(&key)
+        .filter(|e| e.container_name == container_name);
+    let alias = alias
+        .map(str::to_string)
+        .or_else(|| previous.and_then(|e| e.alias.clone()));
+    let env_vars = if env_vars.is_empty() {
+        previous.map(|e| e.env_vars.clone()).unwrap_or_default()
+    } else {
+        env_vars.to_vec()
+    };
+    let dangerous_acknowledged = previous.is_some_and(|e| e.dangerous_acknowledged);
+    let note = previous.and_then(|e| e.note.clone());
+
+    registry.folders.insert(
+        key,
+        ContainerEntry {
+            container_name: container_name.to_string(),
+            folder_paths: paths,
+            created_at: chrono::Local::now().to_rfc3339(),
+            alias,
+            env_vars,
+            dangerous_acknowledged,
+            expires_at: None,
+            note,
+            options: Some(options.clone()),
+        },
+    );
+    save_folder_registry(&registry)?;
+    Ok(())
+}
+
+/// Record the `--ttl` expiry of a container in its registry entry
+fn set_container_expiry(container_name: &str, expires_at: &str) -> Result<()> {
+    let _lock = lock_registry("folder_registry")?;
+    let mut registry = load_folder_registry()?;
+    if let Some(entry) = registry
+        .folders
+        .values_mut()
+        .find(|e| e.container_name == container_name)
+    {
+        entry.expires_at = Some(expires_at.to_string());
+        save_folder_registry(&registry)?;
+    }
+    Ok(())
+}
+
+/// Drop the registry entries of a container that was removed for good
+fn unregister_container(container_name: &str) -> Result<()> {
+    let _lock = lock_registry("folder_registry")?;
+    let mut registry = load_folder_registry()?;
+    let before = registry.folders.len();
+    registry
+        .folders
+        .retain(|_, e| e.container_name != container_name);
+    if registry.folders.len() != before {
+        save_folder_registry(&registry)?;
+    }
+    Ok(())
+}
+
+/// Set or clear a container's note. Returns false if the container isn't registered.
+fn set_container_note(container_name: &str, note: Option<&str>) -> Result<bool> {
+    let _lock = lock_registry("folder_registry")?;
+    let mut registry = load_folder_registry()?;
+    let Some(entry) = registry
+        .folders
+        .values_mut()
+        .find(|e| e.container_name == container_name)
+    else {
+        return Ok(false);
+    };
+    entry.note = note.map(str::to_string);
+    save_folder_registry(&registry)?;
+    Ok(true)
+}
+
+/// Show, set or clear a container's note (`note` command)
+fn note_cmd(container: &str, text: Option<&str>, clear: bool) -> Result<()> {
+    if text.is_none() && !clear {
+        let note = lookup_container_entry(container)?
+            .with_context(|| format!("Container '{}' is not in the folder registry", container))?
+            .1
+            .note;
+        match note {
+            Some(note) => println!("{}", note),
+            None => println!("Container '{}' has no note.", container),
+        }
+        return Ok(());
+    }
+    let text = text.map(str::trim).filter(|t| !t.is_empty());
+    if !clear && text.is_none() {
+        bail!("The note cannot be empty; use --clear to remove it");
+    }
+    if !set_container_note(container, text)? {
+        bail!(CliError::new(
+            ErrorKind::NotFound,
+            format!(
+                "Container '{}' is not in the folder registry. Use 'run' to create it.",
+                container
+            )
+        ));
+    }
+    match text {
+        Some(_) => println!("{} Note saved for '{}'", "✓".green(), container),
+        None => println!("{} Note removed from '{}'", "✓".green(), container),
+    }
+    Ok(())
+}
+
+/// Remember that skip-permissions mode was confirmed for a container so
+/// later runs don't ask again. Unregistered (scratch) containers are skipped.
+fn acknowledge_dangerous(container_name: &str) -> Result<()> {
+    let _lock = lock_registry("folder_registry")?;
+    let mut registry = load_folder_registry()?;
+    let Some(entry) = registry
+        .folders
+        .values_mut()
+        .find(|e| e.container_name == container_name)
+    else {
+        return Ok(());
+    };
+    if !entry.dangerous_acknowledged {
+        entry.dangerous_acknowledged = true;
+        save_folder_registry(&registry)?;
+    }
+    Ok(())
+}
+
+/// Look up container name by folder path (or a folder inside a mapped one)
+fn lookup_container_by_folder(folder: &str) -> Result<Option<String>> {
+    let registry = load_folder_registry()?;
+
+    // First, try exact path match
+    let folder_path = PathBuf::from(folder);
+    if let Ok(canonical) = folder_path.canonicalize() {
+        // Check if this folder is part of any registered container
+        for entry in registry.folders.values() {
+            if entry_matches_folder(entry, &canonical) {
+                return Ok(Some(entry.container_name.clone()));
+            }
+        }
+
+        // Then a container with a parent of this folder mounted, preferring
+        // the deepest (most specific) mapped folder
+        let parent_match = registry
+            .folders
+            .values()
+            .flat_map(|entry| {
+                entry
+                    .folder_paths
+                    .iter()
+                    .map(move |p| (entry, Path::new(p)))
+            })
+            .filter(|(_, mapped)| canonical.starts_with(mapped))
+            .max_by_key(|(_, mapped)| mapped.components().count());
+        if let Some((entry, _)) = parent_match {
+            return Ok(Some(entry.container_name.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Whether a registry entry belongs to a canonical folder path: the folder is
+/// one of its mapped folders, or the container is named after the folder
+fn entry_matches_folder(entry: &ContainerEntry, canonical: &Path) -> bool {
+    let canonical_str = canonical.to_string_lossy().to_string();
+    if entry.folder_paths.contains(&canonical_str) {
+        return true;
+    }
+    // Also check if folder name matches container suffix
+    canonical
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| {
+            let expected = format!("{}-{}", CONTAINER_PREFIX, sanitize_name(name));
+            entry.container_name == expected || entry.container_name.starts_with(&expected)
+        })
+}
+
+/// Containers registered for a folder (e.g. the current directory)
+fn containers_for_folder(folder: &Path) -> Result<Vec<String>> {
+    let canonical = folder
+        .canonicalize()
+        .with_context(|| format!("Cannot access folder: {}", folder.display()))?;
+    let mut names: Vec<String> = load_folder_registry()?
+        .folders
+        .values()
+        .filter(|e| entry_matches_folder(e, &canonical))
+        .map(|e| e.container_name.clone())
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Look up container name by alias
+fn lookup_container_by_alias(alias: &str) -> Result<Option<String>> {
+    let registry = load_folder_registry()?;
+    Ok(registry
+        .folders
+        .values()
+        .find(|e| e.alias.as_deref() == Some(alias))
+        .map(|e| e.container_name.clone()))
+}
+
+/// Find the registry entry (and its key) for a container
+fn lookup_container_entry(container: &str) -> Result<Option<(String, ContainerEntry)>> {
+    let registry = load_folder_registry()?;
+    Ok(registry
+        .folders
+        .into_iter()
+        .find(|(_, e)| e.container_name == container))
+}
+
+/// Ask a yes/no question on stdin, defaulting to no. `--yes` answers yes and
+/// `--no-interactive` answers no without reading stdin.
+fn confirm(question: &str) -> Result<bool> {
+    confirm_on(&mut io::stdout(), question)
+}
+
+/// `confirm`, writing the question to `out` (stderr when stdout carries JSON)
+fn confirm_on(out: &mut dyn Write, question: &str) -> Result<bool> {
+    write!(out, "{} [y/N]: ", question)?;
+    match prompt_mode() {
+        PromptMode::AssumeYes => {
+            writeln!(out, "y {}", "(--yes)".dimmed())?;
+            return Ok(true);
+        }
+        PromptMode::NoInteractive => {
+            writeln!(out, "n {}", "(--no-interactive)".dimmed())?;
+            return Ok(false);
+        }
+        PromptMode::Ask => {}
+    }
+    out.flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
+/// Resolve target (folder path or container name) to container name
+fn resolve_target_to_container(target: Option<&str>) -> Result<String> {
+    match target {
+        None => get_last_session(),
+        Some(t) => {
+            // Check if it's an existing container name
+            let path = PathBuf::from(t);
+            if path.exists() && path.is_dir() {
+                // It's a folder path, look up or derive container name
+                if let Some(name) = lookup_container_by_folder(t)? {
+                    return Ok(name);
+                }
+                // Not registered, derive from folder name
+                return derive_container_name(&[path]);
+            }
+
+            // Check if it's an alias
+            if let Some(name) = lookup_container_by_alias(t)? {
+                return Ok(name);
+            }
+
+            // Check if it looks like a container name (starts with prefix)
+            if t.starts_with(CONTAINER_PREFIX) {
+                return Ok(t.to_string());
+            }
+
+            // Try as folder name without path
+            if let Some(name) = lookup_container_by_folder(t)? {
+                return Ok(name);
+            }
+
+            // Assume it's a container name
+            Ok(t.to_string())
+        }
+    }
+}
+
+/// Get per-container config directory for isolated state
+fn get_container_config_dir(container_name: &str) -> Result<PathBuf> {
+    let config_dir = get_config_dir()?;
+    Ok(config_dir.join("containers").join(container_name))
+}
+
+/// Append an entry to the container's `history.jsonl`
+fn record_history(
+    container: &str,
+    action: &str,
+    prompt: Option<&str>,
+    session_name: Option<&str>,
+    conversation_id: Option<&str>,
+) -> Result<()> {
+    let dir = get_container_config_dir(container)?;
+    std::fs::create_dir_all(&dir)?;
+    let entry = HistoryEntry {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        action: action.to_string(),
+        prompt: prompt.map(str::to_string),
+        session_name: session_name.map(str::to_string),
+        conversation_id: conversation_id.map(str::to_string),
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join("history.jsonl"))?;
+    writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+    Ok(())
+}
+
+fn load_sessions_registry() -> Result<SessionsRegistry> {
+    load_registry("named_sessions")
+}
+
+fn save_sessions_registry(registry: &SessionsRegistry) -> Result<()> {
+    save_registry("named_sessions", registry)
+}
+
+fn save_named_session(name: &str, conversation_id: &str, prompt: Option<&str>) -> Result<()> {
+    let _lock = lock_registry("named_sessions")?;
+    let mut registry = load_sessions_registry()?;
+    registry.sessions.insert(
         name.to_string(),
-    ];
+        NamedSession {
+            conversation_id: conversation_id.to_string(),
+            prompt: prompt.map(str::to_string),
+        },
+    );
+    save_sessions_registry(&registry)?;
+    Ok(())
+}
 
-    for folder in folders {
-        let (abs, fname) = resolve_folder_path(folder)?;
-        args.extend([
-            "-v".to_string(),
-            format!("{}:/home/claude/workspace/{}", abs.display(), fname),
-        ]);
+fn get_named_session(name: &str) -> Result<Option<String>> {
+    let registry = load_sessions_registry()?;
+    Ok(registry
+        .sessions
+        .get(name)
+        .map(|s| s.conversation_id.clone()))
+}
+
+/// Directories (inside the container) where claude-code has stored
+/// conversation history across versions: `projects/<encoded-path>/<uuid>.jsonl`
+/// in current releases, `conversations/` in older ones.
+const CONVERSATION_DIRS: &[&str] = &[
+    "/home/claude/.claude/projects",
+    "/home/claude/.claude/conversations",
+];
+
+/// The in-container history directory the per-container conversations are
+/// mounted on: `history_path` from `config.toml`, or the current default.
+/// It is used unquoted in shell commands and `-v` specs, so only plain path
+/// characters are accepted.
+fn history_path() -> Result<String> {
+    match load_settings()?.history_path {
+        Some(path) if !path.starts_with('/') => {
+            bail!(
+                "history_path in config.toml must be an absolute path, got '{}'",
+                path
+            )
+        }
+        Some(path)
+            if !path
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "/._-".contains(c))
+                || path.split('/').any(|c| c == "..") =>
+        {
+            bail!(
+                "history_path in config.toml may only contain letters, digits, '/', '.', '_' \
+                 and '-' (and no '..'), got '{}'",
+                path
+            )
+        }
+        Some(path) => Ok(path.trim_end_matches('/').to_string()),
+        None => Ok(CONVERSATION_DIRS[0].to_string()),
+    }
+}
+
+/// History directories to search, the configured one first
+fn conversation_dirs() -> Result<Vec<String>> {
+    let mut dirs = vec![history_path()?];
+    for dir in CONVERSATION_DIRS {
+        if !dirs.iter().any(|d| d == dir) {
+            dirs.push(dir.to_string());
+        }
+    }
+    Ok(dirs)
+}
+
+/// Check whether a string looks like a conversation UUID
+fn is_conversation_id(s: &str) -> bool {
+    s.len() == 36
+        && s.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Given a listing of `<mtime> <path>` lines (as produced by `find -printf '%T@ %p\n'`),
+/// return the conversation ID of the most recently modified `<uuid>.jsonl` file
+/// or `<uuid>` directory.
+fn latest_conversation_id(listing: &str) -> Option<String> {
+    listing
+        .lines()
+        .filter_map(|line| {
+            let (mtime, path) = line.trim().split_once(' ')?;
+            let mtime: f64 = mtime.parse().ok()?;
+            let name = path.rsplit('/').next()?;
+            let id = name.strip_suffix(".jsonl").unwrap_or(name);
+            is_conversation_id(id).then(|| (mtime, id.to_string()))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, id)| id)
+}
+
+/// Detect the most recent conversation ID by inspecting the history
+/// directories in the container.
+async fn detect_latest_conversation_id(container: &str) -> Result<Option<String>> {
+    let script = format!(
+        "find {} -maxdepth 3 -printf '%T@ %p\\n' 2>/dev/null",
+        conversation_dirs()?.join(" ")
+    );
+    let output = Command::new("docker")
+        .args(["exec", container, "bash", "-c", &script])
+        .traced()
+        .output()
+        .await?;
+
+    Ok(latest_conversation_id(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Extract a conversations archive into the container's history directory
+/// and return the ID of the conversation to resume. The archive is a tar
+/// (optionally gzipped) laid out like a container's `conversations/`
+/// directory, i.e. `<project>/<uuid>.jsonl`.
+async fn import_conversations(container: &str, archive: &Path) -> Result<String> {
+    if !archive.is_file() {
+        bail!("Export archive not found: {}", archive.display());
+    }
+    let staged = "/tmp/claude-sandbox-import.tar";
+    let status = Command::new("docker")
+        .arg("cp")
+        .arg(archive)
+        .arg(format!("{}:{}", container, staged))
+        .traced()
+        .status()
+        .await?;
+    if !status.success() {
+        bail!("Failed to copy '{}' into the container", archive.display());
+    }
+
+    let listing = Command::new("docker")
+        .args(["exec", container, "tar", "-tf", staged])
+        .traced()
+        .output()
+        .await?;
+    if !listing.status.success() {
+        bail!(
+            "'{}' is not a valid tar archive: {}",
+            archive.display(),
+            String::from_utf8_lossy(&listing.stderr).trim()
+        );
+    }
+    let ids: Vec<String> = String::from_utf8_lossy(&listing.stdout)
+        .lines()
+        .filter_map(|path| {
+            let name = path.trim_end_matches('/').rsplit('/').next()?;
+            let id = name.strip_suffix(".jsonl")?;
+            is_conversation_id(id).then(|| id.to_string())
+        })
+        .collect();
+    if ids.is_empty() {
+        bail!(
+            "No conversations (<uuid>.jsonl files) found in '{}'",
+            archive.display()
+        );
+    }
+
+    let output = Command::new("docker")
+        .args([
+            "exec",
+            container,
+            "bash",
+            "-c",
+            &format!(
+                "mkdir -p {history} && tar -xf {staged} -C {history} && rm -f {staged}",
+                history = history_path()?
+            ),
+        ])
+        .traced()
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!(
+            "Failed to extract '{}': {}",
+            archive.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // Prefer the most recently modified of the imported conversations
+    match detect_latest_conversation_id(container).await? {
+        Some(id) if ids.contains(&id) => Ok(id),
+        _ => Ok(ids[0].clone()),
+    }
+}
+
+/// Find a conversation in the stored history of any sandbox container.
+/// Returns the owning container and the project directory holding it.
+fn find_stored_conversation(id: &str) -> Result<(String, PathBuf)> {
+    if !is_conversation_id(id) {
+        bail!("'{}' is not a conversation ID", id);
+    }
+    let containers_dir = get_config_dir()?.join("containers");
+    let containers = std::fs::read_dir(&containers_dir)
+        .into_iter()
+        .flatten()
+        .flatten();
+    for container in containers {
+        let conversations = container.path().join("conversations");
+        for project in std::fs::read_dir(&conversations)
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            if project.path().join(format!("{}.jsonl", id)).is_file() {
+                let name = container.file_name().to_string_lossy().to_string();
+                return Ok((name, project.path()));
+            }
+        }
+    }
+    bail!(CliError::new(
+        ErrorKind::NotFound,
+        format!(
+            "Conversation {} not found in the history of any sandbox under {}",
+            id,
+            containers_dir.display()
+        ),
+    ))
+}
+
+/// Recursively copy a directory, leaving files that already exist untouched
+fn copy_dir_no_clobber(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)?.flatten() {
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_no_clobber(&entry.path(), &target)?;
+        } else if !target.exists() {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy a conversation (and its attachment directory) into a container's
+/// history under the same project name, so `claude --resume` can pick it up
+fn replay_conversation(container: &str, id: &str, project_dir: &Path) -> Result<()> {
+    let project = project_dir
+        .file_name()
+        .context("Invalid conversation directory")?;
+    let target = get_container_config_dir(container)?
+        .join("conversations")
+        .join(project);
+    if target == project_dir {
+        return Ok(());
+    }
+    std::fs::create_dir_all(&target)?;
+    let file = format!("{}.jsonl", id);
+    if !target.join(&file).exists() {
+        std::fs::copy(project_dir.join(&file), target.join(&file))?;
+    }
+    let attachments = project_dir.join(id);
+    if attachments.is_dir() {
+        copy_dir_no_clobber(&attachments, &target.join(id))?;
+    }
+    Ok(())
+}
+
+/// Seed the (bind-mounted) host `.claude` directory with defaults baked into
+/// the image. The main container mounts the host directory over
+/// `/home/claude/.claude`, which shadows the image's pre-installed plugins;
+/// this one-shot copy populates the host directory before that mount takes
+/// effect. `cp -rn` (no-clobber) ensures existing user state is never
+/// overwritten on subsequent runs.
+async fn seed_image_defaults(global_claude_dir: &std::path::Path, image: &str) -> Result<()> {
+    // Skip if the host dir already contains a plugins/ subdirectory.
+    if global_claude_dir.join("plugins").exists() {
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        "Seeding default plugins from image into host state dir (first run)...".cyan()
+    );
+
+    let mount_spec = format!("{}:/seed-target", global_claude_dir.display());
+    let output = Command::new("docker")
+        .args([
+            "run",
+            "--rm",
+            "-v",
+            &mount_spec,
+            image,
+            "bash",
+            "-c",
+            "cp -rn /home/claude/.claude/. /seed-target/",
+        ])
+        .traced()
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        eprintln!(
+            "{} Seed step failed (continuing): {}",
+            "⚠".yellow(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SessionAction {
+    NewSession,
+    Continue,
+}
+
+fn get_dockerfile_content() -> String {
+    let template = r#"# syntax=docker/dockerfile:1.7
+FROM debian:bookworm-slim
+
+ENV HOME=/home/claude
+ENV LANG=C.UTF-8
+ENV LC_ALL=C.UTF-8
+ENV TERM=xterm-256color
+ENV DEBIAN_FRONTEND=noninteractive
+
+# System dependencies (with BuildKit apt cache mounts for fast rebuilds).
+# Includes search/QoL tools Claude uses constantly: ripgrep, fd, tree, bat, vim, less, jq.
+RUN --mount=type=cache,target=/var/cache/apt,sharing=locked \
+    --mount=type=cache,target=/var/lib/apt/lists,sharing=locked \
+    rm -f /etc/apt/apt.conf.d/docker-clean && \
+    apt-get update && apt-get install -y --no-install-recommends \
+    curl \
+    wget \
+    git \
+    ca-certificates \
+    sudo \
+    gnupg \
+    build-essential \
+    pkg-config \
+    libssl-dev \
+    xz-utils \
+    jq \
+    ripgrep \
+    fd-find \
+    tree \
+    bat \
+    less \
+    vim \
+    htop \
+    python3 \
+    python3-pip \
+    python3-venv \
+    python3-numpy \
+    python3-pandas \
+    python3-requests \
+    && ln -sf /usr/bin/fdfind /usr/local/bin/fd \
+    && ln -sf /usr/bin/batcat /usr/local/bin/bat
+
+# GitHub CLI (gh) from official repo
+RUN --mount=type=cache,target=/var/cache/apt,sharing=locked \
+    --mount=type=cache,target=/var/lib/apt/lists,sharing=locked \
+    mkdir -p -m 755 /etc/apt/keyrings && \
+    curl -fsSL https://cli.github.com/packages/githubcli-archive-keyring.gpg \
+        -o /etc/apt/keyrings/githubcli-archive-keyring.gpg && \
+    chmod go+r /etc/apt/keyrings/githubcli-archive-keyring.gpg && \
+    echo "deb [arch=$(dpkg --print-architecture) signed-by=/etc/apt/keyrings/githubcli-archive-keyring.gpg] https://cli.github.com/packages stable main" \
+        > /etc/apt/sources.list.d/github-cli.list && \
+    apt-get update && apt-get install -y --no-install-recommends gh
+
+# Create user and directories. Grant passwordless sudo so Claude can install
+# additional tools at runtime without leaving the container.
+RUN useradd -m -s /bin/bash -d /home/claude claude && \
+    mkdir -p /home/claude/workspace /home/claude/.claude /home/claude/.config /home/claude/.local/bin && \
+    echo 'claude ALL=(ALL) NOPASSWD:ALL' > /etc/sudoers.d/claude && \
+    chmod 440 /etc/sudoers.d/claude
+
+# Default Claude config: preinstall context7 MCP server and pre-allow common
+# read-only commands so Claude doesn't prompt for every routine call.
+RUN cat > /home/claude/.claude.json <<'JSON'
+{
+  "mcpServers": {
+    "context7": {
+      "command": "npx",
+      "args": ["-y", "@upstash/context7-mcp@latest"]
+    }
+  }
+}
+JSON
+
+RUN cat > /home/claude/.claude/settings.json <<'JSON'
+{
+  "permissions": {
+    "allow": [
+      "Bash(ls:*)",
+      "Bash(cat:*)",
+      "Bash(pwd)",
+      "Bash(echo:*)",
+      "Bash(rg:*)",
+      "Bash(fd:*)",
+      "Bash(tree:*)",
+      "Bash(bat:*)",
+      "Bash(jq:*)",
+      "Bash(wc:*)",
+      "Bash(head:*)",
+      "Bash(tail:*)",
+      "Bash(file:*)",
+      "Bash(which:*)",
+      "Bash(env)",
+      "Bash(git status:*)",
+      "Bash(git diff:*)",
+      "Bash(git log:*)",
+      "Bash(git branch:*)",
+      "Bash(git show:*)",
+      "Bash(git remote:*)",
+      "Bash(gh pr view:*)",
+      "Bash(gh pr list:*)",
+      "Bash(gh issue view:*)",
+      "Bash(gh issue list:*)",
+      "Bash(gh repo view:*)",
+      "Bash(python3 --version)",
+      "Bash(node --version)",
+      "Bash(npm --version)",
+      "Bash(cargo --version)",
+      "Bash(rustc --version)",
+      "Bash(forge --version)"
+    ]
+  }
+}
+JSON
+
+RUN echo '{}' > /home/claude/.claude.json.backup && \
+    chown -R claude:claude /home/claude
+
+USER claude
+WORKDIR /home/claude
+
+# Set up PATH for all tools
+ENV PATH="/home/claude/.local/bin:/home/claude/.cargo/bin:/home/claude/.foundry/bin:${PATH}"
+ENV RUSTUP_HOME=/home/claude/.rustup
+ENV CARGO_HOME=/home/claude/.cargo
+ENV NVM_DIR=/home/claude/.nvm
+
+# Install rustup and Rust (stable toolchain)
+RUN curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y --default-toolchain stable
+
+# Install Foundry (forge, cast, anvil)
+RUN curl -L https://foundry.paradigm.xyz | bash && \
+    /home/claude/.foundry/bin/foundryup
+
+# Install nvm and latest Node.js (npm cache mount speeds up reinstalls)
+RUN --mount=type=cache,target=/home/claude/.npm,uid=1000,gid=1000 \
+    curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.40.1/install.sh | bash && \
+    bash -c "source $NVM_DIR/nvm.sh && nvm install node"
+
+# Create symlinks for node/npm/npx to be accessible without sourcing nvm
+RUN bash -c "source $NVM_DIR/nvm.sh && \
+    ln -sf \$(which node) /home/claude/.local/bin/node && \
+    ln -sf \$(which npm) /home/claude/.local/bin/npm && \
+    ln -sf \$(which npx) /home/claude/.local/bin/npx"
+
+# Install claude-code via official installer
+RUN curl -fsSL https://claude.ai/install.sh | bash
+
+# Pre-install the official Anthropic plugin marketplace and a curated plugin
+# set. These commands write to /home/claude/.claude/, which is later seeded
+# into the bind-mounted host state dir on first container start. Failures
+# (e.g. claude CLI requires auth) are logged but do not fail the build,
+# so the image is still usable without plugins.
+__PLUGIN_INSTALL_BLOCK__
+
+# Setup bashrc for interactive shells
+RUN echo 'export PATH=\"/home/claude/.local/bin:/home/claude/.cargo/bin:/home/claude/.foundry/bin:\$PATH\"' >> /home/claude/.bashrc && \
+    echo 'export NVM_DIR=\"\$HOME/.nvm\"' >> /home/claude/.bashrc && \
+    echo '[ -s \"\$NVM_DIR/nvm.sh\" ] && . \"\$NVM_DIR/nvm.sh\"' >> /home/claude/.bashrc
+
+# Verify all installations (fails the build if any tool is missing)
+RUN cargo --version && rustc --version && \
+    forge --version && \
+    node --version && npm --version && \
+    rg --version | head -1 && \
+    fd --version && \
+    bat --version | head -1 && \
+    tree --version | head -1 && \
+    jq --version && \
+    gh --version | head -1 && \
+    python3 --version && \
+    pip3 --version && \
+    sudo -n true
+
+WORKDIR /home/claude/workspace
+
+CMD ["tail", "-f", "/dev/null"]
+"#;
+
+    let plugin_list = DEFAULT_PLUGINS.join(" ");
+    let plugin_block = format!(
+        "RUN (claude plugin marketplace add {source} && \\\n     \
+         for p in {list}; do \\\n         \
+         claude plugin install \"${{p}}@{name}\" || \\\n             \
+         echo \"WARN: plugin install failed: ${{p}}\"; \\\n     \
+         done) || echo \"WARN: plugin pre-install skipped (marketplace add failed)\"",
+        source = PLUGIN_MARKETPLACE_SOURCE,
+        list = plugin_list,
+        name = PLUGIN_MARKETPLACE_NAME,
+    );
+    template.replace("__PLUGIN_INSTALL_BLOCK__", &plugin_block)
+}
+
+/// Get the host user's `UID:GID` for `docker run --user`
+#[cfg(unix)]
+fn host_user_spec() -> Result<String> {
+    let id = |flag: &str| -> Result<String> {
+        let output = std::process::Command::new("id")
+            .arg(flag)
+            .output()
+            .context("Failed to run 'id'")?;
+        if !output.status.success() {
+            bail!("'id {}' failed", flag);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    Ok(format!("{}:{}", id("-u")?, id("-g")?))
+}
+
+#[cfg(not(unix))]
+fn host_user_spec() -> Result<String> {
+    bail!("--match-user is only supported on Unix hosts")
+}
+
+/// Resolve a `--group-add` value to a numeric GID. Names are looked up on the
+/// host, since the group usually doesn't exist inside the image.
+fn resolve_group_id(group: &str) -> Result<String> {
+    if group.parse::<u32>().is_ok() {
+        return Ok(group.to_string());
+    }
+    // getent also covers groups from LDAP/NSS; /etc/group is the fallback
+    let entry = std::process::Command::new("getent")
+        .args(["group", group])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+        .or_else(|| {
+            std::fs::read_to_string("/etc/group")
+                .ok()
+                .and_then(|groups| {
+                    groups
+                        .lines()
+                        .find(|line| line.split(':').next() == Some(group))
+                        .map(str::to_string)
+                })
+        });
+    entry
+        .and_then(|line| line.trim().split(':').nth(2).map(str::to_string))
+        .filter(|gid| gid.parse::<u32>().is_ok())
+        .with_context(|| format!("Unknown group '{}' in --group-add", group))
+}
+
+/// Resolve a folder path to an absolute path and extract the folder name
+fn resolve_folder_path(folder: &PathBuf) -> Result<(PathBuf, String)> {
+    let abs = std::fs::canonicalize(folder)
+        .with_context(|| format!("Cannot access folder: {}", folder.display()))?;
+
+    let fname = abs
+        .file_name()
+        .map(|n| n.to_str().unwrap_or("project"))
+        .unwrap_or("project")
+        .to_string();
+
+    Ok((abs, fname))
+}
+
+/// Failures with a stable process exit code, so scripts can tell them apart.
+/// Every other error exits with 1.
+#[derive(Debug, Clone, Copy)]
+enum ErrorKind {
+    DockerUnavailable = 2,
+    NotFound = 3,
+    PortConflict = 4,
+    BuildFailed = 5,
+}
+
+/// An error carrying an [`ErrorKind`]; its message is shown as-is
+#[derive(Debug)]
+struct CliError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl CliError {
+    fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        CliError {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Process exit code for an error returned from a command
+fn exit_code(err: &anyhow::Error) -> i32 {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<CliError>())
+        .map_or(1, |e| e.kind as i32)
+}
+
+/// Point every docker command this process runs at `context`
+fn use_docker_context(context: &str) -> Result<()> {
+    let output = std::process::Command::new("docker")
+        .args(["context", "inspect", context])
+        .stdout(Stdio::null())
+        .traced()
+        .output()
+        .context("Failed to run 'docker context inspect'")?;
+    if !output.status.success() {
+        bail!(CliError::new(
+            ErrorKind::NotFound,
+            format!(
+                "Docker context '{}' not found (see 'docker context ls')",
+                context
+            )
+        ));
+    }
+    // Set before any other docker command is spawned; children inherit it
+    std::env::set_var("DOCKER_CONTEXT", context);
+    Ok(())
+}
+
+async fn check_docker() -> Result<()> {
+    let status = Command::new("docker")
+        .arg("info")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .traced()
+        .status()
+        .await?;
+    if !status.success() {
+        bail!(CliError::new(
+            ErrorKind::DockerUnavailable,
+            "Docker is not running. Please start Docker and try again."
+        ));
+    }
+    Ok(())
+}
+
+async fn image_exists(image: &str) -> Result<bool> {
+    let output = Command::new("docker")
+        .args(["image", "inspect", image])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .traced()
+        .status()
+        .await?;
+    Ok(output.success())
+}
+
+async fn container_exists(name: &str) -> Result<bool> {
+    let output = Command::new("docker")
+        .args(["container", "inspect", name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .traced()
+        .status()
+        .await?;
+    Ok(output.success())
+}
+
+async fn container_running(name: &str) -> Result<bool> {
+    let output = Command::new("docker")
+        .args(["inspect", "-f", "{{.State.Running}}", name])
+        .traced()
+        .output()
+        .await?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "true")
+}
+
+/// List the names of sandbox containers, optionally only the running ones
+async fn list_sandbox_containers(running_only: bool) -> Result<Vec<String>> {
+    let all: &[&str] = if running_only { &[] } else { &["-a"] };
+    docker_ps_sandboxes(
+        &SandboxSelector::default(),
+        &[all, &["--format", "{{.Names}}"]].concat(),
+    )
+    .await
+}
+
+/// Open the Dockerfile in `$VISUAL`/`$EDITOR`. Returns whether the build
+/// should go ahead: false if the editor failed, or if the file was left
+/// unchanged and the user declined to build anyway. Messages go to stderr
+/// with `json` so they don't mix with the JSON events.
+fn edit_dockerfile(path: &std::path::Path, original: &str, json: bool) -> Result<bool> {
+    let mut out: Box<dyn Write> = if json {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    };
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    writeln!(out, "Opening {} in {}...", path.display(), editor)?;
+    // $EDITOR may include arguments (e.g. "code --wait")
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().context("Empty $EDITOR")?;
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to launch editor '{}'", editor))?;
+    if !status.success() {
+        writeln!(out, "{} Editor exited with an error", "✗".red())?;
+        return Ok(false);
+    }
+    if std::fs::read_to_string(path)? == original {
+        return confirm_on(&mut out, "Dockerfile unchanged. Build anyway?");
+    }
+    // build_image regenerates the file every time
+    writeln!(
+        out,
+        "{}",
+        "The edited Dockerfile is used for this build only; the next build regenerates it."
+            .yellow()
+    )?;
+    Ok(true)
+}
+
+/// Translate a BuildKit `--progress=plain` line into a JSON event.
+/// Lines look like `#5 [ 2/14] RUN ...`, `#5 DONE 1.2s`, `#5 CACHED`, `#5 ERROR: ...`
+/// or, while pulling a base image, `#5 sha256:ab12... 12.58MB / 29.15MB 0.8s`;
+/// command output lines (`#5 0.123 ...`) are skipped.
+fn build_progress_event(line: &str) -> Option<serde_json::Value> {
+    let rest = line.strip_prefix('#')?;
+    let (id, rest) = rest.split_once(' ')?;
+    let id: u32 = id.parse().ok()?;
+    if rest.starts_with('[') {
+        Some(serde_json::json!({ "event": "step", "id": id, "name": rest }))
+    } else if let Some(duration) = rest.strip_prefix("DONE ") {
+        Some(serde_json::json!({ "event": "step_done", "id": id, "duration": duration }))
+    } else if rest == "CACHED" {
+        Some(serde_json::json!({ "event": "step_cached", "id": id }))
+    } else if let Some(layer) = rest.strip_prefix("sha256:") {
+        let fields: Vec<&str> = layer.split_whitespace().collect();
+        match fields.as_slice() {
+            [digest, current, "/", total, ..] => Some(serde_json::json!({
+                "event": "pull_progress",
+                "id": id,
+                "layer": &digest[..digest.len().min(12)],
+                "current": current,
+                "total": total,
+            })),
+            _ => None,
+        }
+    } else {
+        rest.strip_prefix("ERROR")
+            .map(|msg| serde_json::json!({ "event": "step_error", "id": id, "message": msg.trim_start_matches(':').trim() }))
+    }
+}
+
+/// Outcome of one `docker build` run
+struct BuildAttempt {
+    success: bool,
+    /// Name of the step that failed, when progress output was parsed
+    failed_step: Option<String>,
+    /// Last lines of the build output, for failure reports
+    tail: Vec<String>,
+}
+
+/// Number of build output lines kept for failure reports
+const BUILD_LOG_TAIL: usize = 40;
+
+/// Run `docker build` with plain progress output (`cmd` must include
+/// `--progress=plain`), passing each line to `on_line`
+async fn run_build_plain(cmd: &mut Command, mut on_line: impl FnMut(&str)) -> Result<BuildAttempt> {
+    use tokio::io::AsyncBufReadExt;
+
+    cmd.stdout(Stdio::null()).stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    let stderr = child
+        .stderr
+        .take()
+        .context("Failed to capture build output")?;
+    let mut steps: HashMap<u64, String> = HashMap::new();
+    let mut failed_step = None;
+    let mut tail = std::collections::VecDeque::with_capacity(BUILD_LOG_TAIL);
+    let mut lines = tokio::io::BufReader::new(stderr).lines();
+    while let Some(line) = lines.next_line().await? {
+        if let Some(event) = build_progress_event(&line) {
+            let id = event["id"].as_u64().unwrap_or_default();
+            match event["event"].as_str() {
+                Some("step") => {
+                    steps.insert(id, event["name"].as_str().unwrap_or_default().to_string());
+                }
+                Some("step_error") => failed_step = steps.get(&id).cloned(),
+                _ => {}
+            }
+        }
+        on_line(&line);
+        if tail.len() == BUILD_LOG_TAIL {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+    Ok(BuildAttempt {
+        success: child.wait().await?.success(),
+        failed_step,
+        tail: tail.into(),
+    })
+}
+
+/// Run `docker build` with plain progress output, re-emitting steps as JSON lines
+async fn run_build_json(cmd: &mut Command) -> Result<BuildAttempt> {
+    run_build_plain(cmd, |line| {
+        if let Some(event) = build_progress_event(line) {
+            println!("{}", Versioned::new(event));
+        }
+    })
+    .await
+}
+
+/// Run `docker build` behind a spinner showing the current step. The full
+/// log is only printed if the build fails.
+async fn run_build_spinner(cmd: &mut Command) -> Result<BuildAttempt> {
+    let spinner = spinner("Building image");
+    // Base image of each `FROM` step, to name it while its layers are pulled
+    let mut base_images: HashMap<u64, String> = HashMap::new();
+    let mut pulling = None;
+    let attempt = run_build_plain(cmd, |line| {
+        let (Some(pb), Some(event)) = (&spinner, build_progress_event(line)) else {
+            return;
+        };
+        let id = event["id"].as_u64().unwrap_or_default();
+        if let Some(name) = event["name"].as_str() {
+            if let Some((_, image)) = name.split_once("] FROM ") {
+                let image = image.split('@').next().unwrap_or(image);
+                let image = image.strip_prefix("docker.io/library/").unwrap_or(image);
+                base_images.insert(id, image.to_string());
+            }
+            pb.set_message(name.to_string());
+        } else if event["event"] == "pull_progress" {
+            let image = base_images.get(&id).map_or("base image", String::as_str);
+            if pulling.as_deref() != Some(image) {
+                pb.println(format!("Pulling {}...", image));
+                pulling = Some(image.to_string());
+            }
+            pb.set_message(format!(
+                "Pulling {} (layer {}: {} / {})",
+                image,
+                event["layer"].as_str().unwrap_or_default(),
+                event["current"].as_str().unwrap_or_default(),
+                event["total"].as_str().unwrap_or_default()
+            ));
+        }
+    })
+    .await?;
+    drop(spinner);
+    if !attempt.success {
+        eprintln!("{}", "Build output (last lines):".bold());
+        for line in &attempt.tail {
+            eprintln!("{}", line);
+        }
+    }
+    Ok(attempt)
+}
+
+/// A spinner with elapsed time for slow steps, or None when stdout is not a
+/// terminal or `--verbose` is set (so command output isn't interleaved)
+fn spinner(message: &str) -> Option<indicatif::ProgressBar> {
+    if !io::stdout().is_terminal() || VERBOSE.get().copied().unwrap_or(false) {
+        return None;
+    }
+    // Cleared when dropped, including on early returns
+    let pb = indicatif::ProgressBar::new_spinner().with_finish(indicatif::ProgressFinish::AndClear);
+    pb.set_style(
+        indicatif::ProgressStyle::with_template("{spinner:.cyan} [{elapsed}] {msg}")
+            .unwrap_or_else(|_| indicatif::ProgressStyle::default_spinner()),
+    );
+    pb.set_message(message.to_string());
+    pb.enable_steady_tick(std::time::Duration::from_millis(100));
+    Some(pb)
+}
+
+async fn build_image(options: &BuildOptions) -> Result<()> {
+    for arg in &options.build_args {
+        validate_build_arg(arg)?;
+    }
+    // --quiet prints only the image ID and the success line
+    if !options.json && !options.quiet {
+        println!("{}", "Building Claude Code sandbox image...".cyan());
+    }
+    let image = options.tag.as_deref().unwrap_or(IMAGE_NAME);
+    let config_dir = get_config_dir()?;
+    std::fs::create_dir_all(&config_dir)?;
+    let dockerfile_path = config_dir.join("Dockerfile");
+    let content = get_dockerfile_content();
+    std::fs::write(&dockerfile_path, &content)?;
+    if options.edit && !edit_dockerfile(&dockerfile_path, &content, options.json)? {
+        if options.json {
+            eprintln!("Aborted.");
+        } else {
+            println!("Aborted.");
+        }
+        return Ok(());
+    }
+    // Docker predefines the proxy build args; values come from the environment
+    let (proxy_vars, needs_host_gateway) =
+        proxy_env(options.proxy.as_deref(), options.inherit_proxy);
+    let show_spinner =
+        !options.quiet && io::stdout().is_terminal() && !VERBOSE.get().copied().unwrap_or(false);
+    let build_command = |no_cache: bool| {
+        let mut cmd = Command::new("docker");
+        // BuildKit is required for `# syntax=` and `RUN --mount=type=cache` in the Dockerfile.
+        cmd.env("DOCKER_BUILDKIT", "1");
+        cmd.args(["build", "-t", image]);
+        if needs_host_gateway {
+            cmd.args(["--add-host", "host.docker.internal:host-gateway"]);
+        }
+        for (key, _) in &proxy_vars {
+            cmd.args(["--build-arg", key]);
+        }
+        cmd.envs(proxy_vars.clone());
+        for arg in &options.build_args {
+            cmd.args(["--build-arg", arg]);
+        }
+        if no_cache {
+            cmd.arg("--no-cache");
+        }
+        if options.quiet {
+            cmd.arg("--quiet");
+        }
+        if options.json || show_spinner {
+            cmd.arg("--progress=plain");
+        }
+        cmd.args([
+            "-f",
+            dockerfile_path.to_str().unwrap(),
+            config_dir.to_str().unwrap(),
+        ]);
+        cmd.traced();
+        cmd
+    };
+    let mut cmd = build_command(options.no_cache);
+
+    // Retries reuse the layers of every step that completed (even with
+    // --no-cache, which only applies to the first attempt), so only the
+    // failed step and those after it are rebuilt
+    let mut failures = 0;
+    loop {
+        let attempt = if options.json {
+            run_build_json(&mut cmd).await?
+        } else if options.quiet {
+            // Capture output so only the image ID (or the error) is shown
+            let output = cmd.output().await?;
+            if output.status.success() {
+                println!("{}", String::from_utf8_lossy(&output.stdout).trim());
+            } else {
+                eprintln!("{}", String::from_utf8_lossy(&output.stderr).trim());
+            }
+            BuildAttempt {
+                success: output.status.success(),
+                failed_step: None,
+                tail: Vec::new(),
+            }
+        } else if show_spinner {
+            run_build_spinner(&mut cmd).await?
+        } else {
+            BuildAttempt {
+                success: cmd.status().await?.success(),
+                failed_step: None,
+                tail: Vec::new(),
+            }
+        };
+        if attempt.success {
+            break;
+        }
+
+        failures += 1;
+        let at_step = attempt
+            .failed_step
+            .as_deref()
+            .map(|s| format!(" at step {}", s))
+            .unwrap_or_default();
+        if options.json {
+            println!(
+                "{}",
+                Versioned::new(serde_json::json!({
+                    "event": "error",
+                    "success": false,
+                    "failed_step": attempt.failed_step,
+                    "stderr_tail": attempt.tail.join("\n"),
+                    "attempt": failures,
+                }))
+            );
+        } else if !options.quiet {
+            println!("{} Build failed{}", "✗".red(), at_step);
+        }
+        let retry = if failures <= options.retry {
+            true
+        } else {
+            !options.json
+                && !options.quiet
+                && io::stdin().is_terminal()
+                && prompt_mode() == PromptMode::Ask
+                && confirm("Retry the build? Completed steps are cached.")?
+        };
+        if !retry {
+            bail!(CliError::new(
+                ErrorKind::BuildFailed,
+                format!("Failed to build Docker image{}", at_step)
+            ));
+        }
+        if !options.json && !options.quiet {
+            println!(
+                "{}",
+                format!("Retrying build (attempt {})...", failures + 1).cyan()
+            );
+        }
+        cmd = build_command(false);
+    }
+
+    if options.json {
+        let output = Command::new("docker")
+            .args(["image", "inspect", "-f", "{{.Id}} {{.Size}}", image])
+            .traced()
+            .output()
+            .await?;
+        let inspect = String::from_utf8_lossy(&output.stdout);
+        let mut fields = inspect.split_whitespace();
+        let id = fields.next().unwrap_or_default();
+        let size: Option<u64> = fields.next().and_then(|s| s.parse().ok());
+        println!(
+            "{}",
+            Versioned::new(serde_json::json!({
+                "event": "success",
+                "success": true,
+                "image": image,
+                "id": id,
+                "size": size,
+            }))
+        );
+        if options.prune_images {
+            prune_dangling_images(&dangling_images().await?).await?;
+        }
+        return Ok(());
+    }
+    println!("{}", "Image built successfully!".green());
+
+    let dangling = dangling_images().await?;
+    if !dangling.is_empty() {
+        let size = image_sizes(&dangling).await;
+        if options.prune_images {
+            prune_dangling_images(&dangling).await?;
+            println!(
+                "{} Removed {} dangling image(s), reclaiming up to {}",
+                "✓".green(),
+                dangling.len(),
+                format_size(size)
+            );
+        } else {
+            println!(
+                "{}",
+                format!(
+                    "{} dangling image(s) from earlier builds use up to {}. \
+                     Remove them with 'claude-sandbox build --prune-images'.",
+                    dangling.len(),
+                    format_size(size)
+                )
+                .yellow()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// IDs of untagged images (typically left behind by rebuilds)
+async fn dangling_images() -> Result<Vec<String>> {
+    let output = Command::new("docker")
+        .args(["images", "-f", "dangling=true", "-q"])
+        .traced()
+        .output()
+        .await?;
+    let mut ids: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect();
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
+
+/// Combined size of images in bytes. Layers shared between them are
+/// counted more than once, so this is an upper bound.
+async fn image_sizes(ids: &[String]) -> u64 {
+    let Ok(output) = Command::new("docker")
+        .args(["image", "inspect", "-f", "{{.Size}}"])
+        .args(ids)
+        .traced()
+        .output()
+        .await
+    else {
+        return 0;
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| l.trim().parse::<u64>().ok())
+        .sum()
+}
+
+async fn prune_dangling_images(ids: &[String]) -> Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let output = Command::new("docker")
+        .arg("rmi")
+        .args(ids)
+        .traced()
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!(
+            "Failed to remove dangling images: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Format a byte count for display (e.g. "1.4 GB")
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+async fn save_image(out: &std::path::Path, image: Option<&str>) -> Result<()> {
+    check_docker().await?;
+    let image = image.unwrap_or(IMAGE_NAME);
+    if !image_exists(image).await? {
+        bail!(CliError::new(
+            ErrorKind::NotFound,
+            format!(
+                "Image '{}' not found. Build it first with 'claude-sandbox build'.",
+                image
+            )
+        ));
+    }
+    println!(
+        "{}",
+        format!("Saving image '{}' to {}...", image, out.display()).cyan()
+    );
+    let status = Command::new("docker")
+        .args(["save", "-o"])
+        .arg(out)
+        .arg(image)
+        .traced()
+        .status()
+        .await?;
+    if !status.success() {
+        bail!("Failed to save image '{}'", image);
+    }
+    let size = std::fs::metadata(out)?.len();
+    println!(
+        "{} Saved '{}' ({})",
+        "✓".green(),
+        out.display(),
+        format_size(size)
+    );
+    Ok(())
+}
+
+async fn load_image(archive: &std::path::Path) -> Result<()> {
+    check_docker().await?;
+    if !archive.is_file() {
+        bail!("Archive not found: {}", archive.display());
+    }
+    println!(
+        "{}",
+        format!("Loading image from {}...", archive.display()).cyan()
+    );
+    let status = Command::new("docker")
+        .args(["load", "-i"])
+        .arg(archive)
+        .traced()
+        .status()
+        .await?;
+    if !status.success() {
+        bail!("Failed to load image from '{}'", archive.display());
+    }
+    println!("{} Image loaded", "✓".green());
+    Ok(())
+}
+
+async fn start_container(
+    name: &str,
+    folders: &[PathBuf],
+    options: &ContainerOptions,
+) -> Result<()> {
+    // Per-container directory for isolated conversation history
+    let container_config_dir = get_container_config_dir(name)?;
+    std::fs::create_dir_all(&container_config_dir)?;
+
+    // Global config directory for shared settings (theme, preferences)
+    let global_config_dir = get_config_dir()?;
+    std::fs::create_dir_all(&global_config_dir)?;
+
+    let mut args = vec![
+        "run".to_string(),
+        "-d".to_string(),
+        "--name".to_string(),
+        name.to_string(),
+    ];
+
+    for folder in folders {
+        let (abs, fname) = resolve_folder_path(folder)?;
+        args.extend([
+            "-v".to_string(),
+            format!("{}:/home/claude/workspace/{}", abs.display(), fname),
+        ]);
+    }
+    if options.scratch {
+        args.extend([
+            "--tmpfs".to_string(),
+            "/home/claude/workspace:exec,mode=1777".to_string(),
+        ]);
+    }
+    // Mounted next to the project folders but not part of the container's
+    // identity (name, registry key)
+    if !options.no_default_folders {
+        for (path, read_only) in default_mount_specs()? {
+            let Ok((abs, fname)) = resolve_folder_path(&path) else {
+                println!(
+                    "{} Skipping default mount '{}': folder not found",
+                    "⚠".yellow(),
+                    path.display()
+                );
+                continue;
+            };
+            if folders
+                .iter()
+                .any(|f| resolve_folder_path(f).is_ok_and(|(_, n)| n == fname))
+            {
+                println!(
+                    "{} Skipping default mount '{}': a mapped folder is already named '{}'",
+                    "⚠".yellow(),
+                    abs.display(),
+                    fname
+                );
+                continue;
+            }
+            let mode = if read_only { ":ro" } else { "" };
+            args.extend([
+                "-v".to_string(),
+                format!("{}:/home/claude/workspace/{}{}", abs.display(), fname, mode),
+            ]);
+        }
+    }
+    if options.shared_tmp {
+        let shared_dir = global_config_dir.join("shared");
+        std::fs::create_dir_all(&shared_dir)?;
+        args.extend([
+            "-v".to_string(),
+            format!("{}:/home/claude/shared", shared_dir.display()),
+        ]);
+    }
+
+    let image = options.image.as_deref().unwrap_or(IMAGE_NAME);
+
+    // With --no-default-mounts the container gets none of the shared global
+    // state (auth, settings, app config) and must be authenticated afresh
+    if !options.no_default_mounts {
+        // Mount global .claude directory (for auth, settings, etc.)
+        let global_claude_dir = global_config_dir.join(".claude");
+        std::fs::create_dir_all(&global_claude_dir)?;
+
+        // Seed image-baked defaults (e.g. pre-installed plugins) into the host
+        // dir before the main container's bind mount shadows them. No-op once
+        // the host dir is populated.
+        seed_image_defaults(&global_claude_dir, image).await?;
+
+        args.extend([
+            "-v".to_string(),
+            format!("{}:/home/claude/.claude", global_claude_dir.display()),
+        ]);
+
+        // Mount .claude.json files (GLOBAL - shared settings like theme)
+        let claude_json = global_config_dir.join(".claude.json");
+        let claude_json_backup = global_config_dir.join(".claude.json.backup");
+        if !claude_json.exists() {
+            std::fs::write(&claude_json, "{}")?;
+        }
+        if !claude_json_backup.exists() {
+            std::fs::write(&claude_json_backup, "{}")?;
+        }
+        args.extend([
+            "-v".to_string(),
+            format!("{}:/home/claude/.claude.json", claude_json.display()),
+        ]);
+        args.extend([
+            "-v".to_string(),
+            format!(
+                "{}:/home/claude/.claude.json.backup",
+                claude_json_backup.display()
+            ),
+        ]);
+
+        // Mount .config directory (GLOBAL - shared app settings)
+        let config_app_dir = global_config_dir.join(".config");
+        std::fs::create_dir_all(&config_app_dir)?;
+        args.extend([
+            "-v".to_string(),
+            format!("{}:/home/claude/.config", config_app_dir.display()),
+        ]);
+    }
+
+    // Mount per-container conversations directory (overlay for isolated history)
+    let container_conversations = container_config_dir.join("conversations");
+    std::fs::create_dir_all(&container_conversations)?;
+    args.extend([
+        "-v".to_string(),
+        format!("{}:{}", container_conversations.display(), history_path()?),
+    ]);
+
+    if let Some(ref m) = options.memory {
+        args.extend(["--memory".to_string(), m.clone()]);
+    }
+    if let Some(ref m) = options.memory_swap {
+        args.extend(["--memory-swap".to_string(), m.clone()]);
+    }
+    if let Some(ref m) = options.memory_reservation {
+        args.extend(["--memory-reservation".to_string(), m.clone()]);
+    }
+    if let Some(ref c) = options.cpus {
+        args.extend(["--cpus".to_string(), c.clone()]);
+    }
+    if let Some(ref c) = options.cpuset_cpus {
+        args.extend(["--cpuset-cpus".to_string(), c.clone()]);
+    }
+    if let Some(n) = options.pids_limit {
+        args.extend(["--pids-limit".to_string(), n.to_string()]);
+    }
+    if options.match_user {
+        args.extend(["--user".to_string(), host_user_spec()?]);
+    }
+    for group in &options.group_add {
+        args.extend(["--group-add".to_string(), resolve_group_id(group)?]);
+    }
+    if let Some(ref policy) = options.restart_policy {
+        args.extend(["--restart".to_string(), policy.clone()]);
+    }
+
+    // Add port mappings
+    for port in &options.ports {
+        let normalized = normalize_port_mapping(port)?;
+        args.extend(["-p".to_string(), normalized]);
+    }
+    for host in &options.add_hosts {
+        validate_add_host(host)?;
+        args.extend(["--add-host".to_string(), host.clone()]);
+    }
+
+    args.extend(["-e".to_string(), "ANTHROPIC_API_KEY".to_string()]);
+    args.extend(["-e".to_string(), "TERM=xterm-256color".to_string()]);
+    for e in &options.env_vars {
+        args.extend(["-e".to_string(), e.clone()]);
+    }
+    // Secrets are passed by name and set on the docker process' environment,
+    // so the values never appear on a command line
+    let mut secrets = options
+        .env_secrets
+        .iter()
+        .map(|spec| read_env_secret(spec))
+        .collect::<Result<Vec<_>>>()?;
+    // Proxy URLs may embed credentials, so they are passed the same way
+    let (proxy_vars, needs_host_gateway) =
+        proxy_env(options.proxy.as_deref(), options.inherit_proxy);
+    if needs_host_gateway {
+        args.extend([
+            "--add-host".to_string(),
+            "host.docker.internal:host-gateway".to_string(),
+        ]);
+    }
+    secrets.extend(proxy_vars);
+    for (key, _) in &secrets {
+        args.extend(["-e".to_string(), key.clone()]);
+    }
+    // Already passed by name above; this only overrides the host value
+    let api_key = match options.api_key.clone() {
+        Some(key) => Some(key),
+        None => read_api_key(options)?,
+    };
+    if let Some(key) = api_key {
+        secrets.push(("ANTHROPIC_API_KEY".to_string(), key));
+    }
+
+    args.extend(["--label".to_string(), SANDBOX_LABEL.to_string()]);
+    for label in &options.labels {
+        args.extend(["--label".to_string(), label.clone()]);
+    }
+    if let Some(expires_at) = options.expires_at {
+        args.extend([
+            "--label".to_string(),
+            format!("{}={}", EXPIRES_LABEL, expires_at.to_rfc3339()),
+        ]);
+    }
+    let network = if options.isolated {
+        "none"
+    } else {
+        options.network.as_deref().unwrap_or("bridge")
+    };
+    args.extend(["--network".to_string(), network.to_string()]);
+    if let Some(ref profile) = options.seccomp {
+        args.extend([
+            "--security-opt".to_string(),
+            format!("seccomp={}", profile.display()),
+        ]);
+    }
+    for opt in &options.security_opts {
+        args.extend(["--security-opt".to_string(), opt.clone()]);
+    }
+    // `--entrypoint` only takes the executable; the rest replaces the CMD
+    let entrypoint = match options.entrypoint.as_deref() {
+        Some(e) => split_entrypoint(e)?,
+        None => Vec::new(),
+    };
+    if let Some(program) = entrypoint.first() {
+        args.extend(["--entrypoint".to_string(), program.clone()]);
+    }
+    args.push(image.to_string());
+    args.extend(entrypoint.into_iter().skip(1));
+
+    let starting = spinner("Starting container");
+    let output = Command::new("docker")
+        .args(&args)
+        .envs(secrets)
+        .traced()
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = format!("Failed to start container: {}", stderr);
+        if stderr.contains("port is already allocated") || stderr.contains("address already in use")
+        {
+            bail!(CliError::new(ErrorKind::PortConflict, message));
+        }
+        bail!(message);
+    }
+
+    // Wait for container to be ready
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    drop(starting);
+
+    if let Some(ref entrypoint) = options.entrypoint {
+        if !container_running(name).await? {
+            bail!(
+                "Container '{}' exited right after starting. The --entrypoint command ('{}') \
+                 must be a long-running process (e.g. \"sleep infinity\"); see 'docker logs {}'.",
+                name,
+                entrypoint,
+                name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the files under `from` in the container into `to` on the host.
+/// Returns the number of files collected, or `None` if `from` doesn't exist.
+async fn collect_output(container: &str, from: &str, to: &Path) -> Result<Option<usize>> {
+    let listing = Command::new("docker")
+        .args(["exec", container, "find", from, "-type", "f"])
+        .traced()
+        .output()
+        .await?;
+    if !listing.status.success() {
+        return Ok(None);
+    }
+    let files = String::from_utf8_lossy(&listing.stdout).lines().count();
+
+    std::fs::create_dir_all(to)
+        .with_context(|| format!("Failed to create output directory {}", to.display()))?;
+    let output = Command::new("docker")
+        .arg("cp")
+        .arg(format!("{}:{}/.", container, from.trim_end_matches('/')))
+        .arg(to)
+        .traced()
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!(
+            "Failed to copy {} to {}: {}",
+            from,
+            to.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(Some(files))
+}
+
+/// Describe the result of `collect_output` for the user
+fn output_report(collected: Option<usize>, from: &str, to: &Path) -> String {
+    match collected {
+        Some(files) => format!(
+            "{} Collected {} file(s) from {} into {}",
+            "✓".green(),
+            files,
+            from,
+            to.display()
+        ),
+        None => format!(
+            "{} No {} in the container; nothing collected",
+            "⚠".yellow(),
+            from
+        ),
+    }
+}
+
+/// Run a hook command non-interactively in the container, streaming its
+/// output. Returns whether it exited successfully.
+async fn run_container_hook(name: &str, hook: &str, command: &str) -> Result<bool> {
+    println!("{}", format!("Running {} hook: {}", hook, command).cyan());
+    let status = Command::new("docker")
+        .args(["exec", name, "bash", "-lc", command])
+        .stdin(Stdio::null())
+        .traced()
+        .status()
+        .await?;
+    if status.success() {
+        println!("{} {} hook succeeded", "✓".green(), hook);
+    } else {
+        let code = status
+            .code()
+            .map(|c| c.to_string())
+            .unwrap_or_else(|| "signal".to_string());
+        println!("{} {} hook failed (exit status: {})", "✗".red(), hook, code);
+    }
+    Ok(status.success())
+}
+
+/// Trust every repository in the container (`safe.directory = *`) so git
+/// works on mounted folders whose owner differs from the container user
+async fn configure_git_safe_directory(name: &str) -> Result<()> {
+    let status = Command::new("docker")
+        .args([
+            "exec",
+            name,
+            "git",
+            "config",
+            "--global",
+            "--add",
+            "safe.directory",
+            "*",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .traced()
+        .status()
+        .await?;
+    if !status.success() {
+        println!(
+            "{} Could not configure git safe.directory; git may report dubious ownership",
+            "⚠".yellow()
+        );
+    }
+    Ok(())
+}
+
+/// The `default_mounts` from config.toml as (path, read-only) pairs, with `~/` expanded
+fn default_mount_specs() -> Result<Vec<(PathBuf, bool)>> {
+    load_settings()?
+        .default_mounts
+        .iter()
+        .map(|spec| {
+            let (path, read_only) = match spec.strip_suffix(":rw") {
+                Some(path) => (path, false),
+                None => (spec.strip_suffix(":ro").unwrap_or(spec), true),
+            };
+            let path = match path.strip_prefix("~/") {
+                Some(rest) => dirs::home_dir()
+                    .context("Could not find home directory")?
+                    .join(rest),
+                None => PathBuf::from(path),
+            };
+            Ok((path, read_only))
+        })
+        .collect()
+}
+
+/// Name of the per-folder ignore file read by `--copy-in`
+const COPY_IGNORE_FILE: &str = ".claude-sandbox-ignore";
+
+/// Default container directory collected by `run --output-dir`
+const DEFAULT_OUTPUT_PATH: &str = "/home/claude/workspace/out";
+
+/// Match a glob where `*` and `?` stay within one path component and `**`
+/// matches across components
+fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(p: &[u8], t: &[u8]) -> bool {
+        match p {
+            [] => t.is_empty(),
+            [b'*', b'*', rest @ ..] => (0..=t.len()).any(|i| matches(rest, &t[i..])),
+            [b'*', rest @ ..] => {
+                let limit = t.iter().position(|&c| c == b'/').unwrap_or(t.len());
+                (0..=limit).any(|i| matches(rest, &t[i..]))
+            }
+            [b'?', rest @ ..] => t.first().is_some_and(|&c| c != b'/') && matches(rest, &t[1..]),
+            [c, rest @ ..] => t.first() == Some(c) && matches(rest, &t[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), text.as_bytes())
+}
+
+/// Check a path (relative to the copied folder, `/`-separated) against
+/// ignore patterns. Patterns without a `/` match any file or directory name;
+/// a trailing `/` only matches directories.
+fn copy_ignored(patterns: &[String], rel: &str, is_dir: bool) -> bool {
+    let name = rel.rsplit('/').next().unwrap_or(rel);
+    patterns.iter().any(|pattern| {
+        let (pattern, dir_only) = match pattern.strip_suffix('/') {
+            Some(p) => (p, true),
+            None => (pattern.as_str(), false),
+        };
+        if dir_only && !is_dir {
+            return false;
+        }
+        if pattern.contains('/') {
+            glob_match(pattern.trim_start_matches('/'), rel)
+        } else {
+            glob_match(pattern, name)
+        }
+    })
+}
+
+/// Files copied and skipped by `--copy-in`
+#[derive(Default)]
+struct CopyStats {
+    copied: usize,
+    skipped: usize,
+}
+
+/// Copy `src` into `dest`, leaving out ignored paths. Symlinked directories
+/// are not followed.
+fn stage_copy(
+    src: &Path,
+    dest: &Path,
+    rel: &str,
+    patterns: &[String],
+    stats: &mut CopyStats,
+) -> Result<()> {
+    std::fs::create_dir_all(dest)?;
+    for entry in std::fs::read_dir(src)?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let rel = if rel.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", rel, name)
+        };
+        let file_type = entry.file_type()?;
+        let is_dir = file_type.is_dir();
+        if copy_ignored(patterns, &rel, is_dir) {
+            stats.skipped += if is_dir {
+                count_files(&entry.path())
+            } else {
+                1
+            };
+            continue;
+        }
+        if is_dir {
+            stage_copy(&entry.path(), &dest.join(&name), &rel, patterns, stats)?;
+        } else if entry.path().is_file() {
+            std::fs::copy(entry.path(), dest.join(&name))
+                .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+            stats.copied += 1;
+        } else {
+            stats.skipped += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Number of files under a directory (for the skipped count)
+fn count_files(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| match e.file_type() {
+                    Ok(t) if t.is_dir() => count_files(&e.path()),
+                    _ => 1,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Copy a snapshot of a host folder into `/home/claude/workspace/<name>`,
+/// owned by `owner` (the user the container runs as)
+async fn copy_in_folder(container: &str, folder: &Path, owner: &str) -> Result<()> {
+    let (abs, fname) = resolve_folder_path(&folder.to_path_buf())?;
+    let patterns: Vec<String> = std::fs::read_to_string(abs.join(COPY_IGNORE_FILE))
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+
+    let staging = std::env::temp_dir().join(format!(
+        "claude-sandbox-copy-{}-{}",
+        std::process::id(),
+        fname
+    ));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    let mut stats = CopyStats::default();
+    let dest = format!("/home/claude/workspace/{}", fname);
+    let copied = async {
+        stage_copy(&abs, &staging, "", &patterns, &mut stats)?;
+        let status = Command::new("docker")
+            .arg("cp")
+            .arg(format!("{}/.", staging.display()))
+            .arg(format!("{}:{}", container, dest))
+            .stdout(Stdio::null())
+            .traced()
+            .status()
+            .await?;
+        if !status.success() {
+            bail!("Failed to copy '{}' into the container", abs.display());
+        }
+        Ok(())
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&staging);
+    copied?;
+    // docker cp creates the files as root
+    let status = Command::new("docker")
+        .args(["exec", "-u", "root", container, "chown", "-R", owner, &dest])
+        .traced()
+        .status()
+        .await?;
+    if !status.success() {
+        bail!("Failed to chown {} to {} in the container", dest, owner);
+    }
+
+    println!(
+        "{} Copied {} file(s) from {} to {} ({} skipped by {})",
+        "✓".green(),
+        stats.copied,
+        abs.display(),
+        dest,
+        stats.skipped,
+        COPY_IGNORE_FILE
+    );
+    Ok(())
+}
+
+/// Check that `docker exec` works before attaching, so a wedged container
+/// fails fast instead of hanging the interactive session
+async fn probe_exec(container: &str, secs: u64) -> Result<()> {
+    let probe = Command::new("docker")
+        .args(["exec", container, "true"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .traced()
+        .status();
+    match tokio::time::timeout(std::time::Duration::from_secs(secs), probe).await {
+        Ok(status) if status.as_ref().is_ok_and(|s| s.success()) => Ok(()),
+        Ok(_) => bail!(
+            "'docker exec' failed in container '{}'. Check 'claude-sandbox status {}' \
+             or recreate it with 'run --recreate'.",
+            container,
+            container
+        ),
+        Err(_) => bail!(
+            "Could not exec into container '{}' within {}s; it may be wedged. \
+             Try 'claude-sandbox stop {}' and run again, or 'run --recreate'.",
+            container,
+            secs,
+            container
+        ),
+    }
+}
+
+/// Build the `claude` CLI arguments shared by interactive and print mode
+fn claude_args(
+    dangerously_skip_permissions: bool,
+    continue_session: bool,
+    resume: Option<&str>,
+) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if dangerously_skip_permissions {
+        args.push("--dangerously-skip-permissions".to_string());
+    }
+
+    if continue_session {
+        args.push("-c".to_string());
+    } else if let Some(session) = resume {
+        args.push("-r".to_string());
+        args.push(session.to_string());
+    }
+
+    args
+}
+
+fn exec_claude_interactive(
+    name: &str,
+    prompt: Option<&str>,
+    dangerously_skip_permissions: bool,
+    continue_session: bool,
+    resume: Option<&str>,
+    workdir: Option<&str>,
+) -> Result<std::process::ExitStatus> {
+    let mut args = vec!["exec".to_string(), "-it".to_string()];
+    args.extend(detach_keys_args());
+    if let Some(dir) = workdir {
+        args.extend(["-w".to_string(), dir.to_string()]);
+    }
+    args.extend([name.to_string(), "claude".to_string()]);
+    args.extend(claude_args(
+        dangerously_skip_permissions,
+        continue_session,
+        resume,
+    ));
+
+    if let Some(p) = prompt {
+        args.push(p.to_string());
+    }
+
+    // Use std::process::Command for proper TTY handling
+    let status = std::process::Command::new("docker")
+        .args(&args)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .traced()
+        .status()?;
+
+    Ok(status)
+}
+
+/// Resolve `--cwd` (a path relative to the workspace, e.g. "backend" or
+/// "backend/src") to an absolute directory in the container, checking that
+/// it exists inside a mounted folder
+async fn resolve_workdir(container: &str, subdir: &str) -> Result<String> {
+    let relative = subdir.trim_matches('/');
+    if relative.is_empty()
+        || Path::new(subdir).is_absolute()
+        || relative.split('/').any(|c| c == "..")
+    {
+        bail!(
+            "--cwd must be a folder inside the workspace (e.g. the name of a mapped folder), got '{}'",
+            subdir
+        );
+    }
+    let dir = format!("/home/claude/workspace/{}", relative);
+    let status = Command::new("docker")
+        .args(["exec", container, "test", "-d", &dir])
+        .traced()
+        .status()
+        .await?;
+    if !status.success() {
+        bail!(
+            "--cwd '{}' is not a directory in the container's mapped folders ({} does not exist)",
+            subdir,
+            dir
+        );
+    }
+    Ok(dir)
+}
+
+/// Run `claude --print --output-format stream-json` without a TTY, calling
+/// `on_line` with each output line and its parsed JSON (if it is JSON).
+/// Returns whether claude exited successfully.
+async fn exec_claude_stream_json(
+    name: &str,
+    workdir: Option<&str>,
+    claude_args: &[String],
+    prompt: &str,
+    mut on_line: impl FnMut(&str, Option<serde_json::Value>) -> Result<()>,
+) -> Result<bool> {
+    use tokio::io::AsyncBufReadExt;
+
+    let mut child = Command::new("docker")
+        .args(["exec", "-i"])
+        .args(workdir.map(|dir| ["-w", dir]).into_iter().flatten())
+        .args([name, "claude", "--print"])
+        .args(["--output-format", "stream-json", "--verbose"])
+        .args(claude_args)
+        .arg(prompt)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .traced()
+        .spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .context("Failed to capture claude output")?;
+    let mut lines = tokio::io::BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        let event = serde_json::from_str::<serde_json::Value>(&line).ok();
+        on_line(&line, event)?;
+    }
+    Ok(child.wait().await?.success())
+}
+
+/// Asked at the end of a `--summary` session
+const SUMMARY_PROMPT: &str = "Summarize what we did this session as a short list of the changes \
+                              made and any open follow-ups.";
+
+/// Ask Claude for a recap of the conversation just left (the latest one in
+/// `workdir`, or `resume`) and print it. The recap turn runs in a fork, so
+/// the user's conversation doesn't get the summary appended.
+async fn print_session_summary(
+    container: &str,
+    workdir: Option<&str>,
+    resume: Option<&str>,
+) -> Result<()> {
+    println!("\n{}", "Session summary:".bold());
+    let mut args = claude_args(false, resume.is_none(), resume);
+    args.push("--fork-session".to_string());
+    if !stream_claude_print(container, workdir, &args, SUMMARY_PROMPT).await? {
+        println!("{} Could not summarize the session", "⚠".yellow());
+    }
+    Ok(())
+}
+
+/// Run `claude --print` without a TTY, printing the assistant's text as it
+/// streams in. Lines that aren't JSON are passed through unchanged.
+async fn stream_claude_print(
+    name: &str,
+    workdir: Option<&str>,
+    claude_args: &[String],
+    prompt: &str,
+) -> Result<bool> {
+    exec_claude_stream_json(name, workdir, claude_args, prompt, |line, event| {
+        let Some(event) = event else {
+            println!("{}", line);
+            return Ok(());
+        };
+        if event["type"] != "assistant" {
+            return Ok(());
+        }
+        if let Some(content) = event["message"]["content"].as_array() {
+            for block in content.iter().filter(|b| b["type"] == "text") {
+                if let Some(text) = block["text"].as_str() {
+                    println!("{}", text);
+                }
+            }
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Normalize one claude-code `stream-json` message into this tool's events.
+/// Unrecognized messages are passed through as `raw` events.
+fn normalize_claude_event(event: &serde_json::Value) -> Vec<serde_json::Value> {
+    use serde_json::json;
+
+    let blocks = || {
+        event["message"]["content"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+    };
+    match event["type"].as_str() {
+        Some("system") if event["subtype"] == "init" => vec![json!({
+            "event": "session_start",
+            "session_id": event["session_id"],
+            "model": event["model"],
+        })],
+        Some("assistant") => blocks()
+            .iter()
+            .filter_map(|b| match b["type"].as_str() {
+                Some("text") => Some(json!({ "event": "text", "text": b["text"] })),
+                Some("tool_use") => Some(json!({
+                    "event": "tool_use",
+                    "id": b["id"],
+                    "name": b["name"],
+                    "input": b["input"],
+                })),
+                _ => None,
+            })
+            .collect(),
+        Some("user") => blocks()
+            .iter()
+            .filter(|b| b["type"] == "tool_result")
+            .map(|b| {
+                json!({
+                    "event": "tool_result",
+                    "tool_use_id": b["tool_use_id"],
+                    "is_error": b["is_error"].as_bool().unwrap_or(false),
+                })
+            })
+            .collect(),
+        Some("result") => vec![json!({
+            "event": "result",
+            "session_id": event["session_id"],
+            "is_error": event["is_error"],
+            "duration_ms": event["duration_ms"],
+            "num_turns": event["num_turns"],
+            "total_cost_usd": event["total_cost_usd"],
+            "result": event["result"],
+        })],
+        _ => vec![json!({ "event": "raw", "data": event })],
+    }
+}
+
+/// Run the prompt non-interactively and emit normalized JSON events, one per
+/// line, to `log_file` (appending) or stdout
+async fn run_json_logs(
+    name: &str,
+    workdir: Option<&str>,
+    claude_args: &[String],
+    prompt: &str,
+    log_file: Option<&std::path::Path>,
+) -> Result<bool> {
+    let mut out: Box<dyn Write> = match log_file {
+        Some(path) => Box::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Cannot open log file: {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout()),
+    };
+    exec_claude_stream_json(name, workdir, claude_args, prompt, |line, event| {
+        let events = match event {
+            Some(event) => normalize_claude_event(&event),
+            None => vec![serde_json::json!({ "event": "raw", "line": line })],
+        };
+        for event in events {
+            writeln!(out, "{}", Versioned::new(event))?;
+        }
+        out.flush()?;
+        Ok(())
+    })
+    .await
+}
+
+/// Banner width used when the terminal width can't be determined (e.g. piped output)
+const BANNER_WIDTH: usize = 70;
+
+/// Width of the session banner: the terminal's, but no wider than `BANNER_WIDTH`
+fn banner_width() -> usize {
+    terminal_size::terminal_size()
+        .map(|(terminal_size::Width(w), _)| usize::from(w).min(BANNER_WIDTH))
+        .unwrap_or(BANNER_WIDTH)
+}
+
+/// Number of columns `text` takes up, ignoring ANSI color sequences
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+/// Print one banner line after the `│` border, indented by `indent` columns and
+/// wrapped at spaces to fit `width` (continuation lines are indented further)
+fn banner_row(width: usize, indent: usize, text: &str) {
+    let mut lines = vec![String::new()];
+    let mut used = 1 + indent;
+    for word in text.split(' ') {
+        let word_width = visible_width(word);
+        let last = lines.last_mut().expect("lines is never empty");
+        if !last.is_empty() && used + 1 + word_width > width {
+            lines.push(word.to_string());
+            used = 1 + indent + 2 + word_width;
+        } else {
+            if !last.is_empty() {
+                last.push(' ');
+                used += 1;
+            }
+            last.push_str(word);
+            used += word_width;
+        }
+    }
+    for (i, line) in lines.iter().enumerate() {
+        let pad = if i == 0 { indent } else { indent + 2 };
+        println!("{}{}{}", "│".cyan(), " ".repeat(pad), line);
+    }
+}
+
+fn print_banner(
+    container: &str,
+    session_name: Option<&str>,
+    ports: &[String],
+    folders: &[PathBuf],
+    dangerous: bool,
+) {
+    let width = banner_width();
+    println!("\n{}", "═".repeat(width).cyan());
+    if let Some(name) = session_name {
+        banner_row(
+            width,
+            2,
+            &format!(
+                "Claude Code running session '{}' in container '{}'",
+                name.green(),
+                container.blue()
+            ),
+        );
+    } else {
+        banner_row(
+            width,
+            2,
+            &format!("Claude Code running in container '{}'", container.green()),
+        );
+    }
+
+    // Show mapped folders
+    if !folders.is_empty() {
+        banner_row(width, 2, &"Mapped folders:".bold().to_string());
+        for folder in folders {
+            if let Ok((abs, fname)) = resolve_folder_path(folder) {
+                banner_row(
+                    width,
+                    4,
+                    &format!(
+                        "{} {} -> /home/claude/workspace/{}",
+                        "→".green(),
+                        abs.display(),
+                        fname
+                    ),
+                );
+            }
+        }
+    }
+
+    // Show exposed ports
+    if !ports.is_empty() {
+        banner_row(width, 2, &"Exposed ports:".bold().to_string());
+        for port in ports {
+            let normalized = normalize_port_mapping(port).unwrap_or_else(|_| port.clone());
+            banner_row(width, 4, &format!("{} {}", "→".green(), normalized));
+        }
+    }
+
+    if dangerous {
+        banner_row(
+            width,
+            2,
+            &"⚠ PERMISSION PROMPTS DISABLED (--dangerously-skip-permissions)"
+                .red()
+                .bold()
+                .to_string(),
+        );
+    }
+
+    banner_row(
+        width,
+        2,
+        &format!(
+            "Press {} to exit (container keeps running)",
+            "Ctrl+C".yellow().bold()
+        ),
+    );
+    println!("{}", "│".cyan());
+    banner_row(width, 2, "Reconnect with:");
+    let folder_hint = folders
+        .first()
+        .and_then(|f| f.to_str())
+        .unwrap_or(container);
+    if let Some(name) = session_name {
+        banner_row(
+            width,
+            4,
+            &format!(
+                "{} - resume this named session",
+                format!("claude-sandbox continue {} -n {}", folder_hint, name).green()
+            ),
+        );
+    } else {
+        banner_row(
+            width,
+            4,
+            &format!(
+                "{} - continue last conversation",
+                format!("claude-sandbox continue {}", folder_hint).green()
+            ),
+        );
+    }
+    banner_row(
+        width,
+        4,
+        &format!(
+            "{} - resume specific conversation by ID",
+            format!("claude-sandbox resume -t {} <id>", folder_hint).green()
+        ),
+    );
+    println!("{}\n", "═".repeat(width).cyan());
+}
+
+/// Copy the host's claude-code login (`~/.claude.json` and
+/// `~/.claude/.credentials.json`) into the config shared by all sandboxes
+fn import_host_auth() -> Result<()> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    let config_dir = get_config_dir()?;
+    let files = [
+        (home.join(".claude.json"), config_dir.join(".claude.json")),
+        (
+            home.join(".claude").join(".credentials.json"),
+            config_dir.join(".claude").join(".credentials.json"),
+        ),
+    ];
+    if !files.iter().any(|(host, _)| host.is_file()) {
+        bail!(
+            "No claude-code login found on the host ({} is missing)",
+            files[0].0.display()
+        );
+    }
+
+    // A fresh sandbox config holds just "{}" (see start_container)
+    let configured =
+        |path: &Path| std::fs::read_to_string(path).is_ok_and(|c| !matches!(c.trim(), "" | "{}"));
+    if files.iter().any(|(_, sandbox)| configured(sandbox)) {
+        if !io::stdin().is_terminal() && prompt_mode() == PromptMode::Ask {
+            println!(
+                "{}",
+                "⚠ The sandboxes already have a login; not importing the host's (no TTY to confirm)."
+                    .yellow()
+            );
+            return Ok(());
+        }
+        if !confirm("Replace the sandboxes' existing login with the host's?")? {
+            println!("Keeping the existing sandbox login.");
+            return Ok(());
+        }
+    }
+
+    for (host, sandbox) in &files {
+        if !host.is_file() {
+            continue;
+        }
+        if let Some(parent) = sandbox.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(host, sandbox)
+            .with_context(|| format!("Failed to copy {}", host.display()))?;
+    }
+    println!("{} Imported the host's claude-code login", "✓".green());
+    if !files[1].0.is_file() && cfg!(target_os = "macos") {
+        println!(
+            "{}",
+            "  The login token is kept in the macOS Keychain and could not be copied; \
+             run /login in the sandbox if Claude asks."
+                .dimmed()
+        );
+    }
+    Ok(())
+}
+
+async fn run_claude(mut config: RunConfig) -> Result<()> {
+    load_run_defaults()?.apply(&mut config, &load_settings()?);
+    let replay_source = config
+        .replay
+        .as_deref()
+        .map(find_stored_conversation)
+        .transpose()?;
+    config.container.ports = expand_port_ranges(&config.container.ports)?;
+    if let Some(ref m) = config.container.memory {
+        validate_memory_limit("--memory", m)?;
+    }
+    if let Some(ref m) = config.container.memory_swap {
+        if m != "-1" {
+            validate_memory_limit("--memory-swap", m)?;
+        }
+    }
+    if let Some(ref reservation) = config.container.memory_reservation {
+        validate_memory_limit("--memory-reservation", reservation)?;
+        if let Some(ref limit) = config.container.memory {
+            if memory_limit_bytes(reservation) > memory_limit_bytes(limit) {
+                bail!(
+                    "--memory-reservation ({}) must not be larger than --memory ({})",
+                    reservation,
+                    limit
+                );
+            }
+        }
+    }
+    if let Some(ref policy) = config.container.restart_policy {
+        validate_restart_policy(policy)?;
+    }
+    // "unconfined" turns seccomp off; anything else must be a profile file
+    if let Some(profile) = config.container.seccomp.take() {
+        config.container.seccomp = Some(if profile.as_os_str() == "unconfined" {
+            profile
+        } else {
+            profile
+                .canonicalize()
+                .with_context(|| format!("Seccomp profile not found: {}", profile.display()))?
+        });
+    }
+    for opt in &config.container.security_opts {
+        if opt.trim().is_empty() {
+            bail!("--security-opt cannot be empty");
+        }
+        if opt.starts_with("seccomp=") || opt.starts_with("seccomp:") {
+            bail!("Use --seccomp <PROFILE> instead of --security-opt {}", opt);
+        }
+    }
+    for label in &config.container.labels {
+        validate_label("--label", label, false)?;
+    }
+    if let Some(ref path) = config.label_file {
+        let explicit: Vec<String> = config
+            .container
+            .labels
+            .iter()
+            .filter_map(|l| l.split_once('=').map(|(k, _)| k.to_string()))
+            .collect();
+        let from_file = read_label_file(path)?.into_iter().filter(|l| {
+            l.split_once('=')
+                .is_none_or(|(k, _)| !explicit.iter().any(|e| e == k))
+        });
+        config.container.labels.extend(from_file);
+    }
+    if let Some(ref entrypoint) = config.container.entrypoint {
+        split_entrypoint(entrypoint)?;
+    }
+    let final_prompt = match (
+        config.prompt.take(),
+        config.prompt_file.take(),
+        config.prompt_template.take(),
+    ) {
+        (Some(p), _, _) => Some(p),
+        (None, Some(f), _) => Some(std::fs::read_to_string(&f)?),
+        (None, None, Some(t)) => {
+            let template = std::fs::read_to_string(&t)
+                .with_context(|| format!("Cannot read prompt template: {}", t.display()))?;
+            Some(fill_prompt_template(
+                &template,
+                &config.vars,
+                config.allow_unfilled,
+            )?)
+        }
+        (None, None, None) => None,
+    };
+
+    if config.json_logs && final_prompt.is_none() {
+        bail!("--json-logs requires a prompt (-m or -f)");
+    }
+    if config.print && final_prompt.is_none() {
+        bail!("--print requires a prompt (-m or -f)");
+    }
+    if config.container.isolated && !config.container.ports.is_empty() {
+        bail!("--isolated disables networking and cannot be combined with -p/--port");
+    }
+    // Checked here as well as in start_container so a typo is reported before
+    // an existing container is stopped and removed
+    for host in &config.container.add_hosts {
+        validate_add_host(host)?;
+    }
+    // Likewise a missing secret file; start_container reads them again
+    for spec in &config.container.env_secrets {
+        read_env_secret(spec)?;
+    }
+    config.container.api_key = read_api_key(&config.container)?;
+    if config.container.scratch && !config.copy_in.is_empty() {
+        bail!("--copy-in cannot be combined with --scratch: the scratch workspace is an in-memory tmpfs");
+    }
+    // A copy is written into /home/claude/workspace/<name> and chowned as root,
+    // so it must never land in a bind-mounted host folder
+    // Copy-in-only containers have no registry entry to hold an alias or note
+    if !config.copy_in.is_empty() && config.folders.is_empty() {
+        if config.alias.is_some() {
+            bail!("--alias needs at least one mapped folder; a --copy-in-only container isn't registered");
+        }
+        if config.note.is_some() {
+            bail!("--note needs at least one mapped folder; a --copy-in-only container isn't registered");
+        }
+    }
+    if !config.copy_in.is_empty() {
+        let mut mounted: Vec<String> = config
+            .folders
+            .iter()
+            .filter_map(|f| resolve_folder_path(f).ok().map(|(_, name)| name))
+            .collect();
+        if !config.container.no_default_folders {
+            mounted.extend(
+                default_mount_specs()?
+                    .iter()
+                    .filter_map(|(path, _)| resolve_folder_path(path).ok().map(|(_, name)| name)),
+            );
+        }
+        for folder in &config.copy_in {
+            let (abs, name) = resolve_folder_path(folder)?;
+            if mounted.contains(&name) {
+                bail!(
+                    "--copy-in '{}' would be copied to /home/claude/workspace/{}, which is \
+                     already a mounted host folder. Rename one of them.",
+                    abs.display(),
+                    name
+                );
+            }
+        }
+    }
+
+    check_docker().await?;
+
+    if config.import_auth {
+        import_host_auth()?;
+    }
+
+    // Derive container name from folders if not overridden
+    let container_name = match &config.container_override {
+        Some(name) => name.clone(),
+        // Every scratch run gets a fresh workspace, so it needs its own container
+        None if config.container.scratch => format!(
+            "{}-scratch-{}-{}",
+            CONTAINER_PREFIX,
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            std::process::id()
+        ),
+        // Kept apart from a container that bind-mounts the same folders
+        None if config.folders.is_empty() => {
+            format!("{}-copy", derive_container_name(&config.copy_in)?)
+        }
+        None => derive_container_name(&config.folders)?,
+    };
+
+    if config.attach_only && !container_running(&container_name).await? {
+        bail!(
+            "Container '{}' is not running and --attach-only was given. Use 'run' without --attach-only to start it.",
+            container_name
+        );
+    }
+
+    // Skip-permissions mode is confirmed once per container (on a TTY)
+    let mut acknowledged_dangerous = false;
+    if config.dangerously_skip_permissions
+        && !lookup_container_entry(&container_name)?.is_some_and(|(_, e)| e.dangerous_acknowledged)
+    {
+        if prompt_mode() == PromptMode::AssumeYes {
+            acknowledged_dangerous = true;
+        } else if io::stdin().is_terminal() && prompt_mode() == PromptMode::Ask {
+            println!(
+                "{}",
+                "⚠ --dangerously-skip-permissions lets Claude run any command and edit any \
+                 file in the container without asking."
+                    .red()
+                    .bold()
+            );
+            if !confirm(&format!(
+                "Run '{}' without permission prompts?",
+                container_name
+            ))? {
+                println!("Aborted.");
+                return Ok(());
+            }
+            acknowledged_dangerous = true;
+        } else {
+            // Nobody can confirm, so take the safe default
+            bail!(
+                "--dangerously-skip-permissions has not been confirmed for '{}' and no prompt \
+                 can be shown. Pass --yes to confirm it.",
+                container_name
+            );
+        }
+    }
+
+    let image = config.container.image.as_deref().unwrap_or(IMAGE_NAME);
+    if !image_exists(image).await? {
+        if image != IMAGE_NAME {
+            bail!(CliError::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Image '{}' not found. Build it with 'claude-sandbox build --tag {}'.",
+                    image, image
+                )
+            ));
+        }
+        println!("{}", "Image not found, building...".yellow());
+        build_image(&BuildOptions {
+            proxy: config.container.proxy.clone(),
+            inherit_proxy: config.container.inherit_proxy,
+            ..Default::default()
+        })
+        .await?;
+    }
+
+    if let Some(ref alias) = config.alias {
+        if alias.is_empty() {
+            bail!("Alias cannot be empty");
+        }
+        if let Some(owner) = lookup_container_by_alias(alias)? {
+            if owner != container_name {
+                bail!("Alias '{}' is already used by container '{}'", alias, owner);
+            }
+        }
+    }
+
+    // Check if container already exists and is running
+    let container_exists_flag = container_exists(&container_name).await?;
+    let container_running_flag = if container_exists_flag {
+        container_running(&container_name).await?
+    } else {
+        false
+    };
+
+    // If we have a named session, check if it already exists
+    if let Some(ref session_name) = config.session_name {
+        if let Some(conversation_id) = get_named_session(session_name)? {
+            println!(
+                "{}",
+                format!(
+                    "Session '{}' already exists (conversation: {})",
+                    session_name,
+                    &conversation_id[..8.min(conversation_id.len())]
+                )
+                .yellow()
+            );
+            if !confirm("Overwrite with new session?")? {
+                println!("Use 'continue -n {}' to resume it.", session_name);
+                return Ok(());
+            }
+        }
+    }
+
+    // Set when the user asked for ports but we attach to the container as-is
+    let mut kept_running_ports = false;
+
+    // Determine what action to take based on container state
+    let action = if config.recreate {
+        if container_exists_flag {
+            println!(
+                "{}",
+                format!("Recreating container '{}'...", container_name).yellow()
+            );
+        }
+        SessionAction::NewSession
+    } else if container_running_flag {
+        // Check if user specified ports - these require container recreation
+        if !config.container.ports.is_empty() && config.attach_only {
+            println!(
+                "{}",
+                "Ignoring port changes (--attach-only never recreates containers)".yellow()
+            );
+            kept_running_ports = true;
+            SessionAction::Continue
+        } else if !config.container.ports.is_empty() {
+            println!(
+                "{}",
+                format!("Container '{}' is already running.", container_name).yellow()
+            );
+            if confirm(&format!(
+                "Recreate with ports {}?",
+                config.container.ports.join(", ").cyan()
+            ))? {
+                SessionAction::NewSession
+            } else {
+                println!("Attaching without port changes...");
+                kept_running_ports = true;
+                SessionAction::Continue
+            }
+        } else {
+            // No ports specified, auto-attach
+            println!(
+                "{}",
+                format!(
+                    "Container '{}' is already running, attaching...",
+                    container_name
+                )
+                .cyan()
+            );
+            SessionAction::Continue
+        }
+    } else {
+        SessionAction::NewSession
+    };
+
+    if matches!(action, SessionAction::Continue) && !config.copy_in.is_empty() {
+        bail!(
+            "--copy-in only applies when a container is created, and '{}' is already running. \
+             Add --recreate to copy the folders into a fresh container.",
+            container_name
+        );
+    }
+
+    if config.stop_others {
+        for other in list_sandbox_containers(true).await? {
+            if other == container_name {
+                continue;
+            }
+            halt_container(&other, &StopOptions::default()).await?;
+            println!("{} Stopped '{}'", "✓".green(), other);
+        }
+    }
+
+    match action {
+        SessionAction::Continue => {
+            if config.container.expires_at.is_some() {
+                println!(
+                    "{}",
+                    format!(
+                        "⚠ --ttl only applies when a container is created; '{}' keeps its \
+                         current expiry (use --recreate to set a new one)",
+                        container_name
+                    )
+                    .yellow()
+                );
+            }
+            // Just continue the existing session - auto-attach
+            config.continue_session = true;
+        }
+        SessionAction::NewSession => {
+            // Enforce the running container limit (the target itself is replaced, not added)
+            if let Some(max) = config.max_containers {
+                let running: Vec<String> = list_sandbox_containers(true)
+                    .await?
+                    .into_iter()
+                    .filter(|c| c != &container_name)
+                    .collect();
+                if running.len() >= max {
+                    bail!(
+                        "{} sandbox container(s) already running (limit: {}). Stop one first:\n  {}",
+                        running.len(),
+                        max,
+                        running.join("\n  ")
+                    );
+                }
+            }
+
+            // Need to create a new container
+            if container_exists_flag {
+                // Remove the existing container first
+                if container_running_flag {
+                    println!(
+                        "{}",
+                        format!("Stopping existing container '{}'...", container_name).yellow()
+                    );
+                    Command::new("docker")
+                        .args(["stop", &container_name])
+                        .traced()
+                        .status()
+                        .await?;
+                }
+                Command::new("docker")
+                    .args(["rm", &container_name])
+                    .traced()
+                    .status()
+                    .await?;
+            }
+
+            if config.container.isolated {
+                println!(
+                    "{}",
+                    "⚠ Network isolation enabled (--network none): Claude cannot reach the API \
+                     from inside this container unless you provide a local proxy."
+                        .red()
+                        .bold()
+                );
+            }
+
+            if let Some(ref name) = config.session_name {
+                println!(
+                    "{}",
+                    format!(
+                        "Starting new session '{}' in container '{}'...",
+                        name, container_name
+                    )
+                    .cyan()
+                );
+            } else {
+                println!(
+                    "{}",
+                    format!("Starting new container '{}'...", container_name).cyan()
+                );
+            }
+            if config.container.scratch {
+                println!(
+                    "{}",
+                    "⚠ Scratch mode: /home/claude/workspace is in memory. Nothing in it is \
+                     saved when the container stops."
+                        .red()
+                        .bold()
+                );
+            } else {
+                println!("{}:", "Mapped folders".bold());
+            }
+            for folder in &config.folders {
+                let (abs, fname) = resolve_folder_path(folder)?;
+                println!(
+                    "  {} -> /home/claude/workspace/{}",
+                    abs.display().to_string().blue(),
+                    fname
+                );
+            }
+
+            if !config.container.ports.is_empty() {
+                println!("{}:", "Exposed ports".bold());
+                for port in &config.container.ports {
+                    let normalized = normalize_port_mapping(port)?;
+                    println!("  {} {}", "→".green(), normalized);
+                }
+            }
+
+            // Recreating without -e reuses the variables stored for this container
+            if config.container.env_vars.is_empty() && !config.container.scratch {
+                if let Some((_, entry)) = lookup_container_entry(&container_name)? {
+                    config.container.env_vars = entry.env_vars;
+                }
+            }
+
+            start_container(&container_name, &config.folders, &config.container).await?;
+
+            if !config.copy_in.is_empty() {
+                let owner = if config.container.match_user {
+                    host_user_spec()?
+                } else {
+                    "claude:claude".to_string()
+                };
+                for folder in &config.copy_in {
+                    copy_in_folder(&container_name, folder, &owner).await?;
+                }
+            }
+
+            // Register the container with its folders (scratch containers have none).
+            // Copy-in snapshots aren't tied to the host folders, so they aren't registered;
+            // their --ttl still applies through the expiry label
+            if !config.container.scratch && !config.folders.is_empty() {
+                register_container(
+                    &container_name,
+                    &config.folders,
+                    config.alias.as_deref(),
+                    &config.container,
+                )?;
+                if let Some(expires_at) = config.container.expires_at {
+                    set_container_expiry(&container_name, &expires_at.to_rfc3339())?;
+                }
+            }
+
+            // Bind-mounted repos are owned by the host UID, which git rejects
+            // as "dubious ownership" unless they are marked safe
+            if !config.no_git_safe {
+                configure_git_safe_directory(&container_name).await?;
+            }
+
+            if let Some(cmd) = config.post_start.take() {
+                if !run_container_hook(&container_name, "post-start", &cmd).await?
+                    && !config.post_start_ignore_errors
+                {
+                    bail!(
+                        "Post-start command failed; not attaching. Container '{}' is still running \
+                         (use --post-start-ignore-errors to attach anyway).",
+                        container_name
+                    );
+                }
+            }
+        }
+    }
+
+    if acknowledged_dangerous {
+        acknowledge_dangerous(&container_name)?;
+    }
+    if let Some(ref note) = config.note {
+        if !set_container_note(&container_name, Some(note))? {
+            eprintln!(
+                "{} Container '{}' isn't registered; --note was not saved.",
+                "Warning:".yellow(),
+                container_name
+            );
+        }
+    }
+
+    if let Some(ref archive) = config.from_export {
+        let conversation_id = import_conversations(&container_name, archive).await?;
+        println!(
+            "{} Imported conversation {} from {}",
+            "✓".green(),
+            conversation_id,
+            archive.display()
+        );
+        config.continue_session = false;
+        config.resume = Some(conversation_id);
+    }
+
+    if let (Some(id), Some((source, project_dir))) = (config.replay.take(), replay_source) {
+        replay_conversation(&container_name, &id, &project_dir)?;
+        println!(
+            "{} Replaying conversation {} from '{}'",
+            "✓".green(),
+            id,
+            source
+        );
+        config.continue_session = false;
+        config.resume = Some(id);
+    }
+
+    let workdir = match config.cwd {
+        Some(ref subdir) => Some(resolve_workdir(&container_name, subdir).await?),
+        None => None,
+    };
+
+    // Save the current container as the last used session
+    save_last_session(&container_name)?;
+
+    record_history(
+        &container_name,
+        "run",
+        final_prompt.as_deref(),
+        config.session_name.as_deref(),
+        config.resume.as_deref(),
+    )?;
+
+    if config.json_logs {
+        let args = claude_args(
+            config.dangerously_skip_permissions,
+            config.continue_session,
+            config.resume.as_deref(),
+        );
+        let prompt = final_prompt.as_deref().unwrap_or_default();
+        let success = run_json_logs(
+            &container_name,
+            workdir.as_deref(),
+            &args,
+            prompt,
+            config.log_file.as_deref(),
+        )
+        .await?;
+        if let Some(ref session_name) = config.session_name {
+            if let Some(conv_id) = detect_latest_conversation_id(&container_name).await? {
+                save_named_session(session_name, &conv_id, Some(prompt))?;
+            }
+        }
+        if let Some(ref to) = config.output_dir {
+            let collected = collect_output(&container_name, &config.output_from, to).await?;
+            eprintln!("{}", output_report(collected, &config.output_from, to));
+        }
+        if !success {
+            bail!("claude exited with an error");
+        }
+        return Ok(());
+    }
+
+    if config.print {
+        let args = claude_args(
+            config.dangerously_skip_permissions,
+            config.continue_session,
+            config.resume.as_deref(),
+        );
+        let prompt = final_prompt.as_deref().unwrap_or_default();
+        let success =
+            stream_claude_print(&container_name, workdir.as_deref(), &args, prompt).await?;
+        if let Some(ref session_name) = config.session_name {
+            if let Some(conv_id) = detect_latest_conversation_id(&container_name).await? {
+                save_named_session(session_name, &conv_id, Some(prompt))?;
+            }
+        }
+        if let Some(ref to) = config.output_dir {
+            let collected = collect_output(&container_name, &config.output_from, to).await?;
+            eprintln!("{}", output_report(collected, &config.output_from, to));
+        }
+        if !success {
+            bail!("claude exited with an error");
+        }
+        return Ok(());
+    }
+
+    if let Some(secs) = config.attach_timeout {
+        probe_exec(&container_name, secs).await?;
+    }
+
+    // The requested ports were not applied, so show what is actually published
+    let live_ports;
+    let banner_ports = if kept_running_ports {
+        live_ports = published_ports(&container_name).await.unwrap_or_default();
+        let current = if live_ports.is_empty() {
+            "the running container publishes no ports"
+        } else {
+            "showing the container's current mappings"
+        };
+        println!(
+            "{}",
+            format!(
+                "Note: requested ports ({}) were not applied; {}.",
+                config.container.ports.join(", "),
+                current
+            )
+            .yellow()
+        );
+        &live_ports
+    } else {
+        &config.container.ports
+    };
+    if !config.no_banner {
+        print_banner(
+            &container_name,
+            config.session_name.as_deref(),
+            banner_ports,
+            &config.folders,
+            config.dangerously_skip_permissions,
+        );
+    } else if config.dangerously_skip_permissions {
+        // --no-banner only hides the informational banner, never this warning
+        println!(
+            "{}",
+            "⚠ PERMISSION PROMPTS DISABLED (--dangerously-skip-permissions)"
+                .red()
+                .bold()
+        );
+    }
+
+    match final_prompt.as_deref() {
+        Some(prompt) if config.stream_prompt => {
+            println!("{}", "Sending initial prompt...".cyan());
+            let args = claude_args(
+                config.dangerously_skip_permissions,
+                config.continue_session,
+                config.resume.as_deref(),
+            );
+            if !stream_claude_print(&container_name, workdir.as_deref(), &args, prompt).await? {
+                println!("{} Initial prompt did not complete cleanly", "⚠".yellow());
+            }
+            println!("\n{}", "Attaching to the conversation...".cyan());
+            // The prompt created (or extended) the most recent conversation
+            exec_claude_interactive(
+                &container_name,
+                None,
+                config.dangerously_skip_permissions,
+                true,
+                None,
+                workdir.as_deref(),
+            )?;
+        }
+        prompt => {
+            if prompt.is_some() {
+                println!("{}", "Sending initial prompt to Claude...".cyan());
+            }
+            exec_claude_interactive(
+                &container_name,
+                prompt,
+                config.dangerously_skip_permissions,
+                config.continue_session,
+                config.resume.as_deref(),
+                workdir.as_deref(),
+            )?;
+        }
+    }
+
+    // The conversation this session used, when known; --summary recaps it
+    // rather than whichever conversation is newest
+    let mut session_conversation = config.resume.clone().filter(|id| !id.is_empty());
+
+    // If this was a named session, detect and save the conversation ID
+    if let Some(ref session_name) = config.session_name {
+        if let Some(conv_id) = detect_latest_conversation_id(&container_name).await? {
+            save_named_session(session_name, &conv_id, final_prompt.as_deref())?;
+            session_conversation = Some(conv_id.clone());
+            println!(
+                "\n{} Session '{}' saved (conversation: {})",
+                "✓".green(),
+                session_name,
+                &conv_id[..8.min(conv_id.len())]
+            );
+        } else {
+            println!(
+                "\n{} Could not detect conversation ID for session '{}'",
+                "⚠".yellow(),
+                session_name
+            );
+        }
+    }
+
+    if let Some(ref cmd) = config.on_exit {
+        println!();
+        run_container_hook(&container_name, "on-exit", cmd).await?;
+    }
+
+    if let Some(ref to) = config.output_dir {
+        let collected = collect_output(&container_name, &config.output_from, to).await?;
+        println!("\n{}", output_report(collected, &config.output_from, to));
+    }
+
+    if config.summary {
+        print_session_summary(
+            &container_name,
+            workdir.as_deref(),
+            session_conversation.as_deref(),
+        )
+        .await?;
+    }
+
+    println!("\n{} Exited Claude session", "✓".green());
+    println!("  Container '{}' is still running", container_name);
+    // Show how to reconnect
+    let folder_hint = config
+        .folders
+        .first()
+        .and_then(|f| f.to_str())
+        .unwrap_or(&container_name);
+    if let Some(ref name) = config.session_name {
+        println!(
+            "  Use {} to resume this session",
+            format!("claude-sandbox continue {} -n {}", folder_hint, name).yellow()
+        );
+    } else {
+        println!(
+            "  Use {} to continue",
+            format!("claude-sandbox continue {}", folder_hint).yellow()
+        );
+    }
+
+    Ok(())
+}
+
+async fn continue_session_cmd(
+    container: &str,
+    session_name: Option<&str>,
+    cwd: Option<&str>,
+    shell_fallback: bool,
+    prompt: Option<&str>,
+    summary: bool,
+) -> Result<()> {
+    check_docker().await?;
+
+    if !container_running(container).await? {
+        bail!("Container '{container}' is not running. Use 'run' to start it.");
+    }
+    let workdir = match cwd {
+        Some(subdir) => Some(resolve_workdir(container, subdir).await?),
+        None => None,
+    };
+
+    // Save as last used session
+    save_last_session(container)?;
+
+    // A named session resumes its conversation; otherwise the latest is continued
+    let conversation_id = match session_name {
+        Some(name) => Some(get_named_session(name)?.ok_or_else(|| {
+            CliError::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Named session '{}' not found. Use 'run -n {}' to create it.",
+                    name, name
+                ),
+            )
+        })?),
+        None => None,
+    };
+
+    // Send one turn without a TTY; only Claude's answer goes to stdout
+    if let Some(prompt) = prompt {
+        record_history(
+            container,
+            "continue",
+            Some(prompt),
+            session_name,
+            conversation_id.as_deref(),
+        )?;
+        let args = claude_args(false, conversation_id.is_none(), conversation_id.as_deref());
+        if !stream_claude_print(container, workdir.as_deref(), &args, prompt).await? {
+            bail!("claude exited with an error");
+        }
+        return Ok(());
+    }
+
+    if let (Some(name), Some(conversation_id)) = (session_name, conversation_id.as_deref()) {
+        println!(
+            "{}",
+            format!(
+                "Resuming session '{}' (conversation: {}) in container '{}'...",
+                name,
+                &conversation_id[..8.min(conversation_id.len())],
+                container
+            )
+            .cyan()
+        );
+
+        record_history(
+            container,
+            "continue",
+            None,
+            Some(name),
+            Some(conversation_id),
+        )?;
+        let started = std::time::Instant::now();
+        let status = exec_claude_interactive(
+            container,
+            None,
+            false,
+            false,
+            Some(conversation_id),
+            workdir.as_deref(),
+        )?;
+        if shell_fallback && failed_on_start(status, started) {
+            return fallback_shell(container).await;
+        }
+
+        println!("\n{} Exited session '{}'", "✓".green(), name);
+    } else {
+        println!(
+            "{}",
+            format!("Continuing last conversation in container '{container}'...").cyan()
+        );
+
+        record_history(container, "continue", None, None, None)?;
+        let started = std::time::Instant::now();
+        let status =
+            exec_claude_interactive(container, None, false, true, None, workdir.as_deref())?;
+        if shell_fallback && failed_on_start(status, started) {
+            return fallback_shell(container).await;
+        }
+
+        println!("\n{} Exited Claude session", "✓".green());
+    }
+
+    if summary {
+        print_session_summary(container, workdir.as_deref(), conversation_id.as_deref()).await?;
+    }
+
+    println!("  Container '{container}' is still running");
+
+    Ok(())
+}
+
+/// Whether claude exited with an error within a few seconds of starting,
+/// i.e. it could not resume rather than the user ending a session
+fn failed_on_start(status: std::process::ExitStatus, started: std::time::Instant) -> bool {
+    const STARTUP_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
+    !status.success() && started.elapsed() < STARTUP_WINDOW
+}
+
+async fn fallback_shell(container: &str) -> Result<()> {
+    println!(
+        "\n{}",
+        "Claude could not resume a conversation; opening a shell instead...".yellow()
+    );
+    shell_container(container, &[], false, false).await
+}
+
+async fn resume_session_cmd(container: &str, conversation: Option<&str>) -> Result<()> {
+    check_docker().await?;
+
+    if !container_running(container).await? {
+        bail!("Container '{container}' is not running. Use 'run' to start it.");
+    }
+
+    // Save as last used container
+    save_last_session(container)?;
+
+    if let Some(c) = conversation {
+        println!(
+            "{}",
+            format!("Resuming conversation '{c}' in container '{container}'...").cyan()
+        );
+    } else {
+        println!(
+            "{}",
+            format!("Opening conversation picker in container '{container}'...").cyan()
+        );
+    }
+
+    record_history(container, "resume", None, None, conversation)?;
+
+    // If no conversation specified, claude -r will show interactive picker
+    exec_claude_interactive(
+        container,
+        None,
+        false,
+        false,
+        conversation.or(Some("")),
+        None,
+    )?;
+
+    println!("\n{} Exited Claude session", "✓".green());
+    println!("  Container '{container}' is still running");
+
+    Ok(())
+}
+
+/// A conversation stored in a container's history directory
+struct ConversationSummary {
+    id: String,
+    /// claude-code's project directory (the encoded working directory)
+    project: String,
+    modified: chrono::DateTime<chrono::Local>,
+    title: Option<String>,
+}
+
+/// Conversations stored for a container, most recently modified first. They
+/// are read from the host side of the history mount, so the container does
+/// not need to be running.
+fn list_conversations(container: &str) -> Result<Vec<ConversationSummary>> {
+    let dir = get_container_config_dir(container)?.join("conversations");
+    let mut conversations = Vec::new();
+    if !dir.exists() {
+        return Ok(conversations);
+    }
+    for project in std::fs::read_dir(&dir)?.flatten() {
+        if !project.path().is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(project.path())?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(id) = name.strip_suffix(".jsonl") else {
+                continue;
+            };
+            if !is_conversation_id(id) {
+                continue;
+            }
+            conversations.push(ConversationSummary {
+                id: id.to_string(),
+                project: project.file_name().to_string_lossy().to_string(),
+                modified: entry.metadata()?.modified()?.into(),
+                title: conversation_title(&entry.path(), false),
+            });
+        }
+    }
+    conversations.sort_by_key(|c| std::cmp::Reverse(c.modified));
+    Ok(conversations)
+}
+
+/// The title of a conversation transcript: claude-code's `summary` record if
+/// it wrote one, otherwise the start of the first user prompt. With
+/// `first_only`, reading stops at the first user prompt instead of scanning
+/// the whole transcript for a later summary.
+fn conversation_title(path: &Path, first_only: bool) -> Option<String> {
+    use std::io::BufRead;
+
+    let file = std::fs::File::open(path).ok()?;
+    let mut first_prompt = None;
+    for line in io::BufReader::new(file).lines().map_while(Result::ok) {
+        let Ok(record) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+        match record["type"].as_str() {
+            Some("summary") => {
+                if let Some(summary) = record["summary"].as_str() {
+                    return Some(summary.to_string());
+                }
+            }
+            Some("user") if first_prompt.is_none() => {
+                let content = &record["message"]["content"];
+                first_prompt = content
+                    .as_str()
+                    .or_else(|| content[0]["text"].as_str())
+                    .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "));
+                if first_only {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    const MAX_TITLE: usize = 60;
+    first_prompt.map(|p| match p.char_indices().nth(MAX_TITLE) {
+        Some((i, _)) => format!("{}…", &p[..i]),
+        None => p,
+    })
+}
+
+fn print_conversations(container: &str, json: bool) -> Result<()> {
+    let conversations = list_conversations(container)?;
+    if json {
+        let conversations: Vec<_> = conversations
+            .iter()
+            .map(|c| {
+                serde_json::json!({
+                    "id": c.id,
+                    "project": c.project,
+                    "modified": c.modified.to_rfc3339(),
+                    "title": c.title,
+                })
+            })
+            .collect();
+        let result = serde_json::json!({
+            "container": container,
+            "conversations": conversations,
+        });
+        println!("{}", serde_json::to_string_pretty(&Versioned::new(result))?);
+        return Ok(());
+    }
+    if conversations.is_empty() {
+        println!("No conversations stored for container '{}'.", container);
+        return Ok(());
+    }
+    for (i, c) in conversations.iter().enumerate() {
+        println!(
+            "{:>3}. {}  {}  {}",
+            i + 1,
+            c.id.cyan(),
+            c.modified.format("%Y-%m-%d %H:%M"),
+            c.title.as_deref().unwrap_or("(untitled)")
+        );
+    }
+    println!(
+        "\nResume one with: claude-sandbox resume -t {} <ID>",
+        container
+    );
+    Ok(())
+}
+
+/// The readable text of a transcript record: a plain string message, or the
+/// `text` blocks of a structured one (tool calls and results are skipped)
+fn message_text(record: &serde_json::Value) -> Option<String> {
+    let content = &record["message"]["content"];
+    if let Some(text) = content.as_str() {
+        return Some(text.to_string());
+    }
+    let parts: Vec<&str> = content
+        .as_array()?
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("\n"))
+}
+
+/// A one-line excerpt of `text` around the first case-insensitive match of `query`
+fn match_snippet(text: &str, query: &str) -> Option<String> {
+    const CONTEXT: usize = 40;
+    let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let chars: Vec<char> = text.chars().collect();
+    let needle: Vec<char> = query.chars().map(lower).collect();
+    if needle.is_empty() {
+        return None;
+    }
+    let start = chars
+        .windows(needle.len())
+        .position(|w| w.iter().map(|c| lower(*c)).eq(needle.iter().copied()))?;
+    let from = start.saturating_sub(CONTEXT);
+    let to = (start + needle.len() + CONTEXT).min(chars.len());
+    let excerpt: String = chars[from..to].iter().collect();
+    Some(format!(
+        "{}{}{}",
+        if from > 0 { "…" } else { "" },
+        excerpt.split_whitespace().collect::<Vec<_>>().join(" "),
+        if to < chars.len() { "…" } else { "" }
+    ))
+}
+
+/// Find user and assistant messages containing `query` in a container's stored conversations
+fn search_conversations(container: &str, query: &str, json: bool) -> Result<()> {
+    use std::io::BufRead;
+    // Enough to recognize the context without flooding the terminal
+    const MAX_SNIPPETS: usize = 3;
+
+    if query.trim().is_empty() {
+        bail!("The search query cannot be empty");
+    }
+    let dir = get_container_config_dir(container)?.join("conversations");
+    let mut results = Vec::new();
+    for conversation in list_conversations(container)? {
+        let path = dir
+            .join(&conversation.project)
+            .join(format!("{}.jsonl", conversation.id));
+        let file = std::fs::File::open(&path)?;
+        let mut matches = Vec::new();
+        let mut total = 0;
+        for line in io::BufReader::new(file).lines().map_while(Result::ok) {
+            let Ok(record) = serde_json::from_str::<serde_json::Value>(&line) else {
+                continue;
+            };
+            let Some(role @ ("user" | "assistant")) = record["type"].as_str() else {
+                continue;
+            };
+            let Some(snippet) = message_text(&record).and_then(|t| match_snippet(&t, query)) else {
+                continue;
+            };
+            total += 1;
+            if matches.len() < MAX_SNIPPETS {
+                matches.push((role.to_string(), snippet));
+            }
+        }
+        if total > 0 {
+            results.push((conversation, total, matches));
+        }
+    }
+
+    if json {
+        let conversations: Vec<_> = results
+            .iter()
+            .map(|(c, total, matches)| {
+                serde_json::json!({
+                    "id": c.id,
+                    "project": c.project,
+                    "modified": c.modified.to_rfc3339(),
+                    "title": c.title,
+                    "match_count": total,
+                    "matches": matches
+                        .iter()
+                        .map(|(role, snippet)| serde_json::json!({"role": role, "snippet": snippet}))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        let result = serde_json::json!({
+            "container": container,
+            "query": query,
+            "conversations": conversations,
+        });
+        println!("{}", serde_json::to_string_pretty(&Versioned::new(result))?);
+        return Ok(());
+    }
+
+    if results.is_empty() {
+        println!("No conversations in '{}' mention \"{}\".", container, query);
+        return Ok(());
+    }
+    for (c, total, matches) in &results {
+        println!(
+            "{}  {}  {}",
+            c.id.cyan(),
+            c.modified.format("%Y-%m-%d %H:%M"),
+            c.title.as_deref().unwrap_or("(untitled)").bold()
+        );
+        for (role, snippet) in matches {
+            println!("    {} {}", format!("[{}]", role).dimmed(), snippet);
+        }
+        if *total > matches.len() {
+            println!(
+                "    {}",
+                format!("… {} more", total - matches.len()).dimmed()
+            );
+        }
+    }
+    println!(
+        "\nResume one with: claude-sandbox resume -t {} <ID>",
+        container
+    );
+    Ok(())
+}
+
+async fn shell_container(
+    container: &str,
+    command: &[String],
+    json: bool,
+    root: bool,
+) -> Result<()> {
+    check_docker().await?;
+    if !container_running(container).await? {
+        bail!("Container '{container}' is not running");
+    }
+    // Save as last used container
+    save_last_session(container)?;
+    let user: &[&str] = if root { &["-u", "0"] } else { &[] };
+
+    if json {
+        let output = Command::new("docker")
+            .arg("exec")
+            .args(user)
+            .arg(container)
+            .args(command)
+            .stdin(Stdio::null())
+            .traced()
+            .output()
+            .await?;
+        let result = serde_json::json!({
+            "container": container,
+            "command": command,
+            "stdout": String::from_utf8_lossy(&output.stdout),
+            "stderr": String::from_utf8_lossy(&output.stderr),
+            "exit_code": output.status.code(),
+        });
+        println!("{}", serde_json::to_string_pretty(&Versioned::new(result))?);
+        return Ok(());
+    }
+
+    if !command.is_empty() {
+        let status = Command::new("docker")
+            .args(["exec", "-i"])
+            .args(user)
+            .arg(container)
+            .args(command)
+            .traced()
+            .status()
+            .await?;
+        if !status.success() {
+            bail!(
+                "Command exited with status {}",
+                status
+                    .code()
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
+            );
+        }
+        return Ok(());
+    }
+
+    if root {
+        println!(
+            "{}",
+            format!(
+                "⚠ Root shell: changes outside mapped folders are lost when '{container}' \
+                 is recreated. Add them to the Dockerfile to keep them."
+            )
+            .yellow()
+        );
+    }
+    println!(
+        "{}",
+        format!("Opening shell in container '{container}'...").cyan()
+    );
+    std::process::Command::new("docker")
+        .args(["exec", "-it"])
+        .args(detach_keys_args())
+        .args(user)
+        .args([container, "bash"])
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .traced()
+        .status()?;
+    Ok(())
+}
+
+/// Directories `watch-cmd` never descends into (VCS data and build output,
+/// which the watched command itself usually rewrites)
+const WATCH_SKIP_DIRS: &[&str] = &[".git", "target", "node_modules"];
+
+/// Record the modification time and size of every file under `path`
+fn snapshot_files(
+    path: &Path,
+    snapshot: &mut HashMap<PathBuf, (Option<std::time::SystemTime>, u64)>,
+) {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return;
+    };
+    if !meta.is_dir() {
+        snapshot.insert(path.to_path_buf(), (meta.modified().ok(), meta.len()));
+        return;
+    }
+    for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+        let name = entry.file_name();
+        if WATCH_SKIP_DIRS.iter().any(|d| name == *d) {
+            continue;
+        }
+        snapshot_files(&entry.path(), snapshot);
+    }
+}
+
+/// Snapshot of all watched paths, compared between polls to detect changes
+fn watch_snapshot(paths: &[PathBuf]) -> HashMap<PathBuf, (Option<std::time::SystemTime>, u64)> {
+    let mut snapshot = HashMap::new();
+    for path in paths {
+        snapshot_files(path, &mut snapshot);
+    }
+    snapshot
+}
+
+/// Run `cmd` in the container now and again after every change under `paths`
+async fn watch_cmd(container: &str, paths: &[PathBuf], cmd: &[String]) -> Result<()> {
+    const POLL: std::time::Duration = std::time::Duration::from_millis(500);
+    // Wait for the tree to be quiet this long before running (editors often
+    // write several files, or the same file twice, per save)
+    const DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(300);
+
+    check_docker().await?;
+    if !container_running(container).await? {
+        bail!("Container '{container}' is not running");
+    }
+    let paths = if paths.is_empty() {
+        let registry = load_folder_registry()?;
+        let entry = registry
+            .folders
+            .values()
+            .find(|e| e.container_name == container)
+            .with_context(|| {
+                format!("Container '{container}' has no mapped folders; pass --path")
+            })?;
+        entry.folder_paths.iter().map(PathBuf::from).collect()
+    } else {
+        paths.to_vec()
+    };
+    for path in &paths {
+        if !path.exists() {
+            bail!("Watched path not found: {}", path.display());
+        }
+    }
+
+    println!(
+        "{} {} {}",
+        "Watching".cyan(),
+        paths
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        "(Ctrl+C to stop)".dimmed()
+    );
+    loop {
+        println!("\n{} {}", "$".dimmed(), cmd.join(" ").bold());
+        let status = Command::new("docker")
+            .args(["exec", container])
+            .args(cmd)
+            .stdin(Stdio::null())
+            .traced()
+            .status()
+            .await?;
+        let code = status
+            .code()
+            .map(|c| c.to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        if status.success() {
+            println!("{} exit status {}", "✓".green(), code);
+        } else {
+            println!("{} exit status {}", "✗".red(), code);
+        }
+
+        // Changes made by the command itself are part of the new baseline
+        let mut baseline = watch_snapshot(&paths);
+        loop {
+            tokio::time::sleep(POLL).await;
+            let current = watch_snapshot(&paths);
+            if current != baseline {
+                baseline = current;
+                break;
+            }
+        }
+        loop {
+            tokio::time::sleep(DEBOUNCE).await;
+            let current = watch_snapshot(&paths);
+            if current == baseline {
+                break;
+            }
+            baseline = current;
+        }
+    }
+}
+
+/// Run `docker inspect` for a container
+async fn inspect_container(container: &str) -> Result<ContainerInfo> {
+    let output = Command::new("docker")
+        .args(["inspect", container])
+        .traced()
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!(CliError::new(
+            ErrorKind::NotFound,
+            format!("Container '{container}' does not exist")
+        ));
+    }
+    let info: Vec<ContainerInfo> = serde_json::from_slice(&output.stdout)?;
+    info.into_iter()
+        .next()
+        .with_context(|| format!("No inspect data for container '{container}'"))
+}
+
+/// Read the `State` section of `docker inspect` for a container
+async fn inspect_state(container: &str) -> Result<ContainerState> {
+    Ok(inspect_container(container).await?.state)
+}
+
+/// Ports a container actually publishes, as `IP:HOST:CONTAINER` mappings
+/// sorted by container port. IPv6 duplicates of IPv4 bindings are skipped.
+async fn published_ports(container: &str) -> Result<Vec<String>> {
+    let info = inspect_container(container).await?;
+    let mut ports: Vec<(u16, String)> = Vec::new();
+    for (spec, bindings) in info.network_settings.ports {
+        let (port, proto) = spec.split_once('/').unwrap_or((&spec, "tcp"));
+        let Ok(container_port) = port.parse::<u16>() else {
+            continue;
+        };
+        let suffix = if proto == "tcp" {
+            String::new()
+        } else {
+            format!("/{proto}")
+        };
+        let mut seen = Vec::new();
+        for binding in bindings.unwrap_or_default() {
+            if seen.contains(&binding.host_port) {
+                continue;
+            }
+            let ip = if binding.host_ip.is_empty() {
+                "0.0.0.0"
+            } else {
+                &binding.host_ip
+            };
+            ports.push((
+                container_port,
+                format!("{}:{}:{}{}", ip, binding.host_port, container_port, suffix),
+            ));
+            seen.push(binding.host_port);
+        }
+    }
+    ports.sort();
+    Ok(ports.into_iter().map(|(_, mapping)| mapping).collect())
+}
+
+/// Open the URL of a published port in the default browser. The URL is
+/// always printed so it can be copied on headless machines.
+async fn connect_container(container: &str, port: Option<u16>) -> Result<()> {
+    check_docker().await?;
+    if !container_running(container).await? {
+        bail!("Container '{}' is not running", container);
+    }
+    let ports = published_ports(container).await?;
+    // Mappings look like IP:HOST:CONTAINER[/PROTO]
+    let mappings: Vec<(u16, u16)> = ports
+        .iter()
+        .filter(|m| !m.contains('/'))
+        .filter_map(|m| {
+            let mut parts = m.rsplitn(3, ':');
+            let container_port = parts.next()?.parse().ok()?;
+            let host_port = parts.next()?.parse().ok()?;
+            Some((container_port, host_port))
+        })
+        .collect();
+    if mappings.is_empty() {
+        bail!(
+            "Container '{}' publishes no TCP ports. Recreate it with 'run ... -p PORT'.",
+            container
+        );
+    }
+
+    let host_port = match port {
+        None => mappings[0].1,
+        Some(p) => mappings
+            .iter()
+            .find(|(c, _)| *c == p)
+            .or_else(|| mappings.iter().find(|(_, h)| *h == p))
+            .map(|(_, h)| *h)
+            .with_context(|| {
+                let published: Vec<String> = mappings.iter().map(|(c, _)| c.to_string()).collect();
+                format!(
+                    "Port {} is not published by '{}' (published: {})",
+                    p,
+                    container,
+                    published.join(", ")
+                )
+            })?,
+    };
+
+    let url = format!("http://localhost:{}", host_port);
+    println!("{}", url.cyan());
+
+    let opener = if cfg!(target_os = "macos") {
+        Command::new("open").arg(&url).status().await
+    } else if cfg!(windows) {
+        Command::new("cmd")
+            .args(["/C", "start", "", &url])
+            .status()
+            .await
+    } else {
+        Command::new("xdg-open")
+            .arg(&url)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+    };
+    if !opener.map(|s| s.success()).unwrap_or(false) {
+        println!(
+            "{}",
+            "Could not open a browser; open the URL above manually.".yellow()
+        );
     }
+    Ok(())
+}
 
-    // Mount global .claude directory (for auth, settings, etc.)
-    let global_claude_dir = global_config_dir.join(".claude");
-    std::fs::create_dir_all(&global_claude_dir)?;
-
-    // Seed image-baked defaults (e.g. pre-installed plugins) into the host
-    // dir before the main container's bind mount shadows them. No-op once
-    // the host dir is populated.
-    seed_image_defaults(&global_claude_dir).await?;
-
-    args.extend([
-        "-v".to_string(),
-        format!("{}:/home/claude/.claude", global_claude_dir.display()),
-    ]);
-
-    // Mount per-container conversations directory (overlay for isolated history)
-    let container_conversations = container_config_dir.join("conversations");
-    std::fs::create_dir_all(&container_conversations)?;
-    args.extend([
-        "-v".to_string(),
-        format!(
-            "{}:/home/claude/.claude/projects",
-            container_conversations.display()
-        ),
-    ]);
+/// Print the config directory and the state files in it, or open it
+async fn config_dir_cmd(open: bool, path_only: bool) -> Result<()> {
+    let dir = get_config_dir()?;
+    if path_only {
+        println!("{}", dir.display());
+        return Ok(());
+    }
+    if open {
+        if !dir.exists() {
+            bail!("Config directory {} does not exist yet", dir.display());
+        }
+        let opener = if cfg!(target_os = "macos") {
+            Command::new("open").arg(&dir).status().await
+        } else if cfg!(windows) {
+            Command::new("explorer").arg(&dir).status().await
+        } else {
+            Command::new("xdg-open")
+                .arg(&dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .await
+        };
+        match opener {
+            // explorer.exe exits non-zero even when it opened the folder
+            Ok(status) if status.success() || cfg!(windows) => {}
+            _ => bail!("Could not open {} in a file manager", dir.display()),
+        }
+        return Ok(());
+    }
 
-    // Mount .claude.json files (GLOBAL - shared settings like theme)
-    let claude_json = global_config_dir.join(".claude.json");
-    let claude_json_backup = global_config_dir.join(".claude.json.backup");
-    if !claude_json.exists() {
-        std::fs::write(&claude_json, "{}")?;
+    println!(
+        "{} {}",
+        "Config directory:".bold(),
+        dir.display().to_string().cyan()
+    );
+    if !dir.exists() {
+        println!("{}", "  (not created yet)".dimmed());
+        return Ok(());
     }
-    if !claude_json_backup.exists() {
-        std::fs::write(&claude_json_backup, "{}")?;
+    let mut files: Vec<(String, &str)> = Vec::new();
+    for stem in ["folder_registry", "named_sessions"] {
+        for format in [RegistryFormat::Json, RegistryFormat::Yaml] {
+            files.push((format!("{}.{}", stem, format.extension()), "registry"));
+        }
     }
-    args.extend([
-        "-v".to_string(),
-        format!("{}:/home/claude/.claude.json", claude_json.display()),
-    ]);
-    args.extend([
-        "-v".to_string(),
-        format!(
-            "{}:/home/claude/.claude.json.backup",
-            claude_json_backup.display()
+    files.extend([
+        ("Dockerfile".to_string(), "image definition used by build"),
+        ("config.toml".to_string(), "settings"),
+        ("defaults.toml".to_string(), "default run options"),
+        ("last_session".to_string(), "last used container"),
+        (
+            "containers".to_string(),
+            "per-container state and conversations",
         ),
     ]);
+    for (name, what) in files {
+        if dir.join(&name).exists() {
+            println!("  {:<24} {}", name, what.dimmed());
+        }
+    }
+    Ok(())
+}
 
-    // Mount .config directory (GLOBAL - shared app settings)
-    let config_app_dir = global_config_dir.join(".config");
-    std::fs::create_dir_all(&config_app_dir)?;
-    args.extend([
-        "-v".to_string(),
-        format!("{}:/home/claude/.config", config_app_dir.display()),
-    ]);
+/// Print `docker logs` of a container. With a `--grep` text, both output
+/// streams are read line by line and only (non-)matching lines are printed.
+async fn logs_cmd(
+    container: &str,
+    follow: bool,
+    tail: Option<u32>,
+    grep: Option<&str>,
+    invert: bool,
+) -> Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncRead};
 
-    if let Some(m) = memory {
-        args.extend(["--memory".to_string(), m.to_string()]);
+    check_docker().await?;
+    if grep == Some("") {
+        bail!("The --grep text cannot be empty");
+    }
+    let mut cmd = Command::new("docker");
+    cmd.arg("logs");
+    if follow {
+        cmd.arg("--follow");
     }
-    if let Some(c) = cpus {
-        args.extend(["--cpus".to_string(), c.to_string()]);
+    if let Some(n) = tail {
+        cmd.args(["--tail", &n.to_string()]);
     }
+    cmd.arg(container).traced();
 
-    // Add port mappings
-    for port in ports {
-        let normalized = normalize_port_mapping(port)?;
-        args.extend(["-p".to_string(), normalized]);
+    let Some(pattern) = grep else {
+        let status = cmd.status().await?;
+        if !status.success() {
+            bail!("Failed to read logs of container '{}'", container);
+        }
+        return Ok(());
+    };
+
+    // Container stdout and stderr arrive on the matching streams of docker logs
+    fn filter(
+        stream: impl AsyncRead + Unpin + Send + 'static,
+        pattern: String,
+        invert: bool,
+        to_stderr: bool,
+    ) -> tokio::task::JoinHandle<io::Result<()>> {
+        tokio::spawn(async move {
+            let mut lines = tokio::io::BufReader::new(stream).lines();
+            while let Some(line) = lines.next_line().await? {
+                if line.contains(&pattern) == invert {
+                    continue;
+                }
+                let line = if invert {
+                    line
+                } else {
+                    highlight_matches(&line, &pattern)
+                };
+                if to_stderr {
+                    eprintln!("{}", line);
+                } else {
+                    println!("{}", line);
+                }
+            }
+            Ok(())
+        })
     }
 
-    args.extend(["-e".to_string(), "ANTHROPIC_API_KEY".to_string()]);
-    args.extend(["-e".to_string(), "TERM=xterm-256color".to_string()]);
-    for e in env_vars {
-        args.extend(["-e".to_string(), e.clone()]);
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    let stdout = child.stdout.take().context("Failed to capture logs")?;
+    let stderr = child.stderr.take().context("Failed to capture logs")?;
+    let out = filter(stdout, pattern.to_string(), invert, false);
+    let err = filter(stderr, pattern.to_string(), invert, true);
+    out.await??;
+    err.await??;
+    if !child.wait().await?.success() {
+        bail!("Failed to read logs of container '{}'", container);
     }
+    Ok(())
+}
+
+/// Color every occurrence of `pattern` in a line
+fn highlight_matches(line: &str, pattern: &str) -> String {
+    line.split(pattern)
+        .collect::<Vec<_>>()
+        .join(&pattern.red().bold().to_string())
+}
 
-    args.extend(["--network".to_string(), "bridge".to_string()]);
-    args.push(IMAGE_NAME.to_string());
+/// Pause or unpause a container with `docker pause`/`docker unpause`
+async fn pause_container(container: &str, pause: bool) -> Result<()> {
+    check_docker().await?;
+    let state = inspect_state(container).await?;
+    if pause {
+        if !state.running {
+            bail!("Container '{container}' is not running");
+        }
+        if state.paused {
+            println!("Container '{container}' is already paused.");
+            return Ok(());
+        }
+    } else if !state.paused {
+        bail!("Container '{container}' is not paused");
+    }
 
-    let output = Command::new("docker").args(&args).output().await?;
+    let action = if pause { "pause" } else { "unpause" };
+    let output = Command::new("docker")
+        .args([action, container])
+        .traced()
+        .output()
+        .await?;
     if !output.status.success() {
         bail!(
-            "Failed to start container: {}",
+            "Failed to {} container: {}",
+            action,
             String::from_utf8_lossy(&output.stderr)
         );
     }
-
-    // Wait for container to be ready
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-
+    if pause {
+        println!(
+            "{} Container '{}' paused (use 'unpause' to resume)",
+            "✓".green(),
+            container
+        );
+    } else {
+        println!("{} Container '{}' resumed", "✓".green(), container);
+    }
     Ok(())
 }
 
-fn exec_claude_interactive(
-    name: &str,
-    prompt: Option<&str>,
-    dangerously_skip_permissions: bool,
-    continue_session: bool,
-    resume: Option<&str>,
-) -> Result<()> {
-    let mut args = vec![
-        "exec".to_string(),
-        "-it".to_string(),
-        name.to_string(),
-        "claude".to_string(),
-    ];
+/// How `stop` shuts containers down
+#[derive(Default)]
+struct StopOptions {
+    /// Grace period for `docker stop -t`
+    time: Option<u32>,
+    /// Use `docker kill` instead of `docker stop`
+    kill: bool,
+    /// Leave the stopped container in place instead of removing it
+    keep: bool,
+}
 
-    if dangerously_skip_permissions {
-        args.push("--dangerously-skip-permissions".to_string());
-    }
+/// How a container went down
+enum StopOutcome {
+    NotRunning,
+    Graceful,
+    /// Killed, either on request or because the grace period ran out
+    Killed,
+}
 
-    if continue_session {
-        args.push("-c".to_string());
-    } else if let Some(session) = resume {
-        args.push("-r".to_string());
-        args.push(session.to_string());
+impl StopOutcome {
+    fn describe(&self) -> &'static str {
+        match self {
+            StopOutcome::NotRunning => "was not running",
+            StopOutcome::Graceful => "stopped gracefully",
+            StopOutcome::Killed => "was killed",
+        }
     }
+}
 
-    if let Some(p) = prompt {
-        args.push(p.to_string());
+/// Stop a container (without removing it) and report how it went down
+async fn halt_container(container: &str, options: &StopOptions) -> Result<StopOutcome> {
+    if !container_running(container).await? {
+        return Ok(StopOutcome::NotRunning);
     }
+    let mut cmd = Command::new("docker");
+    if options.kill {
+        cmd.args(["kill", container]);
+    } else {
+        cmd.arg("stop");
+        if let Some(t) = options.time {
+            cmd.args(["-t", &t.to_string()]);
+        }
+        cmd.arg(container);
+    }
+    let output = cmd.traced().output().await?;
+    if !output.status.success() {
+        bail!(
+            "Failed to stop container '{}': {}",
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // 137 = 128 + SIGKILL: docker stop escalated after the grace period
+    let state = inspect_state(container).await?;
+    Ok(if options.kill || state.exit_code == 137 {
+        StopOutcome::Killed
+    } else {
+        StopOutcome::Graceful
+    })
+}
 
-    // Use std::process::Command for proper TTY handling
-    std::process::Command::new("docker")
-        .args(&args)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()?;
-
+/// Stop and remove every sandbox whose `--ttl` expiry has passed
+async fn reap_containers(dry_run: bool) -> Result<()> {
+    check_docker().await?;
+    let output = Command::new("docker")
+        .args([
+            "ps",
+            "-a",
+            "--filter",
+            &format!("label={SANDBOX_LABEL}"),
+            "--filter",
+            &format!("label={EXPIRES_LABEL}"),
+            "--format",
+            &format!("{{{{.Names}}}}\t{{{{.Label \"{EXPIRES_LABEL}\"}}}}"),
+        ])
+        .traced()
+        .output()
+        .await?;
+    // Don't mistake a docker failure for "nothing expired" (reap runs from cron)
+    if !output.status.success() {
+        bail!(
+            "Failed to list sandbox containers: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let now = chrono::Local::now();
+    let expired: Vec<(String, String)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(_, expires)| {
+            chrono::DateTime::parse_from_rfc3339(expires).is_ok_and(|t| t <= now)
+        })
+        .map(|(name, expires)| (name.to_string(), expires.to_string()))
+        .collect();
+    if expired.is_empty() {
+        println!("No expired sandboxes.");
+        return Ok(());
+    }
+    for (container, expires) in &expired {
+        if dry_run {
+            println!("{} (expired {})", container, expires);
+            continue;
+        }
+        halt_container(container, &StopOptions::default()).await?;
+        let status = Command::new("docker")
+            .args(["rm", container])
+            .traced()
+            .status()
+            .await?;
+        if status.success() {
+            unregister_container(container)?;
+            println!(
+                "{} Removed '{}' (expired {})",
+                "✓".green(),
+                container,
+                expires
+            );
+        } else {
+            println!("{} Failed to remove '{}'", "✗".red(), container);
+        }
+    }
     Ok(())
 }
 
-fn print_banner(
-    container: &str,
-    session_name: Option<&str>,
-    ports: &[String],
-    folders: &[PathBuf],
-) {
-    println!("\n{}", "═".repeat(70).cyan());
-    if let Some(name) = session_name {
+async fn stop_container(container: &str, options: &StopOptions) -> Result<()> {
+    check_docker().await?;
+    if !container_exists(container).await? {
+        bail!(CliError::new(
+            ErrorKind::NotFound,
+            format!("Container '{container}' does not exist")
+        ));
+    }
+    println!("{}", format!("Stopping container '{container}'...").cyan());
+    let outcome = halt_container(container, options).await?;
+    if options.keep {
         println!(
-            "{}  Claude Code running session '{}' in container '{}'",
-            "│".cyan(),
-            name.green(),
-            container.blue()
+            "{} Container {} and was kept; boot it again with {}",
+            "✓".green(),
+            outcome.describe(),
+            format!("claude-sandbox start {}", container).yellow()
         );
+        return Ok(());
+    }
+    Command::new("docker")
+        .args(["rm", container])
+        .traced()
+        .status()
+        .await?;
+    println!(
+        "{} Container {} and was removed",
+        "✓".green(),
+        outcome.describe()
+    );
+    Ok(())
+}
+
+/// `docker start` a stopped container and show its banner again
+async fn start_container_cmd(container: &str) -> Result<()> {
+    check_docker().await?;
+    if !container_exists(container).await? {
+        bail!(CliError::new(
+            ErrorKind::NotFound,
+            format!(
+                "Container '{container}' does not exist. Create it with 'claude-sandbox run <FOLDER>'."
+            )
+        ));
+    }
+    if container_running(container).await? {
+        println!("Container '{}' is already running.", container);
     } else {
+        println!("{}", format!("Starting container '{container}'...").cyan());
+        let output = Command::new("docker")
+            .args(["start", container])
+            .traced()
+            .output()
+            .await?;
+        if !output.status.success() {
+            bail!(
+                "Failed to start container '{}': {}",
+                container,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    save_last_session(container)?;
+
+    let folders: Vec<PathBuf> = lookup_container_entry(container)?
+        .map(|(_, e)| e.folder_paths.iter().map(PathBuf::from).collect())
+        .unwrap_or_default();
+    let ports = published_ports(container).await.unwrap_or_default();
+    print_banner(container, None, &ports, &folders, false);
+    Ok(())
+}
+
+/// Parse a selection like "1,3-5" into sorted, de-duplicated zero-based indices
+fn parse_selection(input: &str, max: usize) -> Result<Vec<usize>> {
+    let mut selected = Vec::new();
+    for part in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((a, b)) => (
+                a.trim().parse::<usize>().context("Invalid selection")?,
+                b.trim().parse::<usize>().context("Invalid selection")?,
+            ),
+            None => {
+                let n = part.parse::<usize>().context("Invalid selection")?;
+                (n, n)
+            }
+        };
+        if start == 0 || end > max || start > end {
+            bail!("Selection '{}' is out of range (1-{})", part, max);
+        }
+        selected.extend((start - 1)..end);
+    }
+    selected.sort_unstable();
+    selected.dedup();
+    Ok(selected)
+}
+
+async fn stop_all_containers(
+    select: bool,
+    selector: &SandboxSelector,
+    options: &StopOptions,
+) -> Result<()> {
+    check_docker().await?;
+    if !select {
+        println!("{}", "Stopping all Claude sandbox containers...".cyan());
+    }
+
+    // Get all sandbox containers
+    let names = docker_ps_sandboxes(selector, &["-a", "--format", "{{.Names}}"]).await?;
+    let mut containers: Vec<&str> = names.iter().map(String::as_str).collect();
+
+    if containers.is_empty() {
+        println!("No containers to stop.");
+        return Ok(());
+    }
+
+    if select {
+        if prompt_mode() == PromptMode::NoInteractive {
+            println!("Nothing selected (--no-interactive).");
+            return Ok(());
+        }
+        if io::stdin().is_terminal() && prompt_mode() == PromptMode::Ask {
+            println!("{}", "Claude sandbox containers:".bold());
+            for (i, container) in containers.iter().enumerate() {
+                println!("  {} {}", format!("{:>2})", i + 1).cyan(), container);
+            }
+            print!("Containers to stop (e.g. 1,3-5): ");
+            io::stdout().flush()?;
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            let chosen = parse_selection(&input, containers.len())?;
+            if chosen.is_empty() {
+                println!("Nothing selected.");
+                return Ok(());
+            }
+            containers = chosen.into_iter().map(|i| containers[i]).collect();
+        } else if prompt_mode() == PromptMode::AssumeYes {
+            println!("{}", "Stopping all containers (--yes)...".yellow());
+        } else {
+            println!(
+                "{}",
+                "stdin is not a terminal, stopping all containers...".yellow()
+            );
+        }
+    }
+
+    if options.keep {
+        for container in &containers {
+            match halt_container(container, options).await {
+                Ok(outcome) => println!("  '{}' {}", container, outcome.describe()),
+                Err(e) => println!("  {} '{}': {}", "✗".red(), container, e),
+            }
+        }
         println!(
-            "{}  Claude Code running in container '{}'",
-            "│".cyan(),
-            container.green()
+            "{} Stopped {} container(s); they were kept for 'claude-sandbox start'",
+            "✓".green(),
+            containers.len()
         );
+        return Ok(());
     }
 
-    // Show mapped folders
-    if !folders.is_empty() {
-        println!("{}  {}", "│".cyan(), "Mapped folders:".bold());
-        for folder in folders {
-            if let Ok((abs, fname)) = resolve_folder_path(folder) {
-                println!(
-                    "{}    {} {} -> /home/claude/workspace/{}",
-                    "│".cyan(),
-                    "→".green(),
-                    abs.display(),
-                    fname
-                );
-            }
+    for container in &containers {
+        // Stop if running, then remove
+        match halt_container(container, options).await {
+            Ok(outcome) => println!("  Removing '{}' ({})...", container, outcome.describe()),
+            Err(_) => println!("  Removing '{}'...", container),
         }
+        let _ = Command::new("docker")
+            .args(["rm", "-f", container])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .traced()
+            .status()
+            .await;
+    }
+
+    println!("{} Removed {} container(s)", "✓".green(), containers.len());
+    Ok(())
+}
+
+/// Gather all sandbox containers known to docker
+async fn collect_sandboxes(
+    selector: &SandboxSelector,
+    group_by: Option<&str>,
+    with_size: bool,
+) -> Result<Vec<SandboxSummary>> {
+    let mut format = "{{.Names}}\t{{.Status}}\t{{.Ports}}\t{{.CreatedAt}}\t{{.State}}".to_string();
+    // Computing sizes is slow, so only ask for them when needed
+    if with_size {
+        format.push_str("\t{{.Size}}");
     }
+    if let Some(key) = group_by {
+        format.push_str(&format!("\t{{{{.Label {:?}}}}}", key));
+    }
+    let size: &[&str] = if with_size { &["-s"] } else { &[] };
+    let lines =
+        docker_ps_sandboxes(selector, &[&["-a"], size, &["--format", &format]].concat()).await?;
+    Ok(lines
+        .iter()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 5 {
+                return None;
+            }
+            Some(SandboxSummary {
+                name: fields[0].to_string(),
+                status: fields[1].to_string(),
+                ports: fields[2].to_string(),
+                created_at: fields[3].to_string(),
+                running: fields[4] == "running",
+                size: with_size
+                    .then(|| fields.get(5).and_then(|size| parse_docker_size(size)))
+                    .flatten(),
+                group: group_by
+                    .and_then(|_| fields.get(5 + usize::from(with_size)))
+                    .filter(|value| !value.is_empty())
+                    .map(|value| value.to_string()),
+            })
+        })
+        .collect())
+}
 
-    // Show exposed ports
-    if !ports.is_empty() {
-        println!("{}  {}", "│".cyan(), "Exposed ports:".bold());
-        for port in ports {
-            let normalized = normalize_port_mapping(port).unwrap_or_else(|_| port.clone());
-            println!("{}    {} {}", "│".cyan(), "→".green(), normalized);
+/// Print containers as an aligned table with a header row
+fn print_sandbox_table(sandboxes: &[SandboxSummary]) {
+    let headers = ["NAMES", "STATUS", "PORTS", "CREATED AT"];
+    let rows: Vec<[&str; 4]> = sandboxes
+        .iter()
+        .map(|s| [s.name.as_str(), &s.status, &s.ports, &s.created_at])
+        .collect();
+    let mut widths = headers.map(str::len);
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.len());
         }
     }
-
-    println!(
-        "{}  Press {} to exit (container keeps running)",
-        "│".cyan(),
-        "Ctrl+C".yellow().bold()
-    );
-    println!("{}", "│".cyan());
-    println!("{}  Reconnect with:", "│".cyan());
-    let folder_hint = folders
-        .first()
-        .and_then(|f| f.to_str())
-        .unwrap_or(container);
-    if let Some(name) = session_name {
-        println!(
-            "{}    {} - resume this named session",
-            "│".cyan(),
-            format!("claude-sandbox continue {} -n {}", folder_hint, name).green()
-        );
-    } else {
+    for row in std::iter::once(headers).chain(rows) {
         println!(
-            "{}    {} - continue last conversation",
-            "│".cyan(),
-            format!("claude-sandbox continue {}", folder_hint).green()
+            "{:<w0$}   {:<w1$}   {:<w2$}   {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
         );
     }
-    println!(
-        "{}    {} - resume specific conversation by ID",
-        "│".cyan(),
-        format!("claude-sandbox resume -t {} <id>", folder_hint).green()
-    );
-    println!("{}\n", "═".repeat(70).cyan());
 }
 
-async fn run_claude(mut config: RunConfig) -> Result<()> {
+async fn list_sessions(options: &ListOptions) -> Result<()> {
     check_docker().await?;
-
-    if !image_exists().await? {
-        println!("{}", "Image not found, building...".yellow());
-        build_image(false).await?;
+    let filter = &options.filter;
+    let mut sandboxes: Vec<SandboxSummary> = collect_sandboxes(
+        &filter.selector,
+        options.group_by.as_deref(),
+        options.sort == ListSort::Size,
+    )
+    .await?
+    .into_iter()
+    .filter(|s| !filter.running || s.running)
+    .filter(|s| filter.matches_created(s.created()))
+    .filter(|s| {
+        filter
+            .containers
+            .as_ref()
+            .is_none_or(|names| names.contains(&s.name))
+    })
+    .collect();
+    options.sort.apply(&mut sandboxes, options.reverse);
+
+    if options.count {
+        println!("{}", sandboxes.len());
+        return Ok(());
     }
 
-    // Derive container name from folders if not overridden
-    let container_name = match &config.container_override {
-        Some(name) => name.clone(),
-        None => derive_container_name(&config.folders)?,
-    };
+    if filter.containers.is_some() && sandboxes.is_empty() && options.format.is_none() {
+        let cwd = std::env::current_dir()?;
+        println!(
+            "No sandboxes found for {}. Start one with 'claude-sandbox run .'",
+            cwd.display()
+        );
+        return Ok(());
+    }
 
-    // Check if container already exists and is running
-    let container_exists_flag = container_exists(&container_name).await?;
-    let container_running_flag = if container_exists_flag {
-        container_running(&container_name).await?
-    } else {
-        false
-    };
+    if let Some(ref template) = options.format {
+        let folder_registry = load_folder_registry()?;
+        for sandbox in &sandboxes {
+            let entry = folder_registry
+                .folders
+                .values()
+                .find(|e| e.container_name == sandbox.name);
+            let fields = [
+                ("Name", sandbox.name.clone()),
+                (
+                    "Alias",
+                    entry.and_then(|e| e.alias.clone()).unwrap_or_default(),
+                ),
+                ("Status", sandbox.status.clone()),
+                (
+                    "State",
+                    if sandbox.running {
+                        "running"
+                    } else {
+                        "stopped"
+                    }
+                    .to_string(),
+                ),
+                ("Ports", sandbox.ports.clone()),
+                ("CreatedAt", sandbox.created_at.clone()),
+                (
+                    "Folders",
+                    entry.map(|e| e.folder_paths.join(",")).unwrap_or_default(),
+                ),
+                (
+                    "Note",
+                    entry.and_then(|e| e.note.clone()).unwrap_or_default(),
+                ),
+            ];
+            println!("{}", render_template(template, &fields)?);
+        }
+        return Ok(());
+    }
 
-    // If we have a named session, check if it already exists
-    if let Some(ref session_name) = config.session_name {
-        if let Some(conversation_id) = get_named_session(session_name)? {
-            println!(
-                "{}",
-                format!(
-                    "Session '{}' already exists (conversation: {})",
-                    session_name,
-                    &conversation_id[..8.min(conversation_id.len())]
-                )
-                .yellow()
-            );
-            print!("Overwrite with new session? [y/N]: ");
-            io::stdout().flush()?;
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
-            if !input.trim().eq_ignore_ascii_case("y") {
-                println!("Use 'continue -n {}' to resume it.", session_name);
-                return Ok(());
+    println!("{}", "Claude sandbox containers:".bold());
+    match options.group_by {
+        Some(ref key) => {
+            // BTreeMap keeps groups sorted; `None` (no label) sorts first, so
+            // it is printed separately at the end
+            let mut groups: std::collections::BTreeMap<Option<&str>, Vec<SandboxSummary>> =
+                std::collections::BTreeMap::new();
+            for sandbox in &sandboxes {
+                groups
+                    .entry(sandbox.group.as_deref())
+                    .or_default()
+                    .push(sandbox.clone());
+            }
+            let ungrouped = groups.remove(&None);
+            for (value, members) in &groups {
+                println!(
+                    "\n{}",
+                    format!("{}={}", key, value.unwrap_or_default()).cyan()
+                );
+                print_sandbox_table(members);
+            }
+            if let Some(members) = ungrouped {
+                println!("\n{}", "ungrouped".dimmed());
+                print_sandbox_table(&members);
             }
         }
+        None => print_sandbox_table(&sandboxes),
     }
 
-    // Determine what action to take based on container state
-    let action = if container_running_flag {
-        // Check if user specified ports - these require container recreation
-        if !config.ports.is_empty() {
+    // Show the last used container
+    if let Ok(last) = get_last_session() {
+        println!("\n{}: {}", "Last used container".cyan(), last.green());
+    }
+
+    // Show folder mappings
+    let folder_registry = load_folder_registry()?;
+    let mut entries: Vec<&ContainerEntry> = folder_registry
+        .folders
+        .values()
+        .filter(|e| !filter.is_active() || sandboxes.iter().any(|s| s.name == e.container_name))
+        .filter(|e| {
+            let created = chrono::DateTime::parse_from_rfc3339(&e.created_at).ok();
+            filter.matches_created(created)
+        })
+        .collect();
+    // Same order as the table; registered but missing containers go last
+    entries.sort_by_key(|e| {
+        (
+            sandboxes
+                .iter()
+                .position(|s| s.name == e.container_name)
+                .unwrap_or(usize::MAX),
+            e.container_name.clone(),
+        )
+    });
+    if !entries.is_empty() {
+        println!("\n{}", "Folder mappings:".bold());
+        for entry in entries {
+            let folders_str = entry
+                .folder_paths
+                .iter()
+                .map(|p| {
+                    PathBuf::from(p)
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or(p)
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let alias_str = entry
+                .alias
+                .as_deref()
+                .map(|a| format!(" ({})", a))
+                .unwrap_or_default();
+            let note_str = entry
+                .note
+                .as_deref()
+                .map(|n| format!("  {}", truncate_chars(n, 50)))
+                .unwrap_or_default();
             println!(
-                "{}",
-                format!("Container '{}' is already running.", container_name).yellow()
-            );
-            print!(
-                "Recreate with ports {}? [y/N]: ",
-                config.ports.join(", ").cyan()
+                "  {}{} {} [{}]{}",
+                entry.container_name.green(),
+                alias_str.yellow(),
+                "←".cyan(),
+                folders_str.blue(),
+                note_str.dimmed()
             );
-            io::stdout().flush()?;
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
-            if input.trim().eq_ignore_ascii_case("y") {
-                SessionAction::NewSession
-            } else {
-                println!("Attaching without port changes...");
-                SessionAction::Continue
-            }
-        } else {
-            // No ports specified, auto-attach
+        }
+    }
+
+    // Show named sessions
+    let registry = load_sessions_registry()?;
+    if !registry.sessions.is_empty() {
+        println!("\n{}", "Named sessions:".bold());
+        let mut sessions: Vec<_> = registry.sessions.iter().collect();
+        sessions.sort_by_key(|(name, _)| name.as_str());
+        let running: Vec<&str> = sandboxes
+            .iter()
+            .filter(|s| s.running)
+            .map(|s| s.name.as_str())
+            .collect();
+        for (name, session) in sessions {
+            let conv_id = &session.conversation_id;
+            // Prefer the transcript's own title; the stored prompt covers
+            // sessions whose history is gone or whose container is stopped
+            let title = session_transcript_title(&running, conv_id)
+                .or_else(|| session.title())
+                .map(|t| format!(" ({})", t))
+                .unwrap_or_default();
             println!(
-                "{}",
-                format!(
-                    "Container '{}' is already running, attaching...",
-                    container_name
-                )
-                .cyan()
+                "  {} -> {}{}",
+                name.green(),
+                &conv_id[..8.min(conv_id.len())].blue(),
+                title
             );
-            SessionAction::Continue
         }
+    }
+    Ok(())
+}
+
+/// Title of a named session's transcript, looked up in the history of the
+/// given (running) containers. Any failure just means no title.
+fn session_transcript_title(containers: &[&str], conversation_id: &str) -> Option<String> {
+    let file = format!("{}.jsonl", conversation_id);
+    containers.iter().find_map(|container| {
+        let conversations = get_container_config_dir(container)
+            .ok()?
+            .join("conversations");
+        std::fs::read_dir(conversations)
+            .ok()?
+            .flatten()
+            .map(|project| project.path().join(&file))
+            .find(|path| path.is_file())
+            .and_then(|path| conversation_title(&path, true))
+    })
+}
+
+fn reset_state(force: bool) -> Result<()> {
+    let config_dir = get_config_dir()?;
+    if !force {
+        println!(
+            "{}",
+            "This will delete all Claude sandbox state and memory.".yellow()
+        );
+        println!("Config directory: {}", config_dir.display());
+        if !confirm("Continue?")? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+    if config_dir.exists() {
+        std::fs::remove_dir_all(&config_dir)?;
+        println!("{} State reset successfully", "✓".green());
     } else {
-        SessionAction::NewSession
+        println!("No state to reset.");
+    }
+    Ok(())
+}
+
+/// Total size of a file or directory tree in bytes
+fn path_size(path: &Path) -> u64 {
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return 0;
     };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| path_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
 
-    match action {
-        SessionAction::Continue => {
-            // Just continue the existing session - auto-attach
-            config.continue_session = true;
+/// Remove all but the `keep` most recent conversations of a container,
+/// optionally only those older than `older_than`
+fn clean_conversations(container: &str, keep: usize, older_than: Option<&str>) -> Result<()> {
+    let cutoff = older_than
+        .map(|d| {
+            parse_duration(d).map(|d| {
+                // Further back than the clock can go: nothing is that old
+                std::time::SystemTime::now()
+                    .checked_sub(d.to_std().unwrap_or_default())
+                    .unwrap_or(std::time::UNIX_EPOCH)
+            })
+        })
+        .transpose()?;
+    let dir = get_container_config_dir(container)?.join("conversations");
+    if !dir.exists() {
+        println!("No conversations stored for container '{}'.", container);
+        return Ok(());
+    }
+
+    // Conversations live as `<project>/<uuid>.jsonl`, with an optional
+    // `<project>/<uuid>/` directory alongside for attachments
+    let mut conversations: HashMap<String, (std::time::SystemTime, Vec<PathBuf>)> = HashMap::new();
+    for project in std::fs::read_dir(&dir)?.flatten() {
+        if !project.path().is_dir() {
+            continue;
         }
-        SessionAction::NewSession => {
-            // Need to create a new container
-            if container_exists_flag {
-                // Remove the existing container first
-                if container_running_flag {
-                    println!(
-                        "{}",
-                        format!("Stopping existing container '{}'...", container_name).yellow()
-                    );
-                    Command::new("docker")
-                        .args(["stop", &container_name])
-                        .status()
-                        .await?;
-                }
-                Command::new("docker")
-                    .args(["rm", &container_name])
-                    .status()
-                    .await?;
+        for entry in std::fs::read_dir(project.path())?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let id = name.strip_suffix(".jsonl").unwrap_or(&name);
+            if !is_conversation_id(id) {
+                continue;
             }
+            let mtime = entry.metadata()?.modified()?;
+            let slot = conversations
+                .entry(id.to_string())
+                .or_insert((mtime, Vec::new()));
+            slot.0 = slot.0.max(mtime);
+            slot.1.push(entry.path());
+        }
+    }
 
-            if let Some(ref name) = config.session_name {
-                println!(
-                    "{}",
-                    format!(
-                        "Starting new session '{}' in container '{}'...",
-                        name, container_name
-                    )
-                    .cyan()
-                );
-            } else {
-                println!(
-                    "{}",
-                    format!("Starting new container '{}'...", container_name).cyan()
-                );
-            }
-            println!("{}:", "Mapped folders".bold());
-            for folder in &config.folders {
-                let (abs, fname) = resolve_folder_path(folder)?;
-                println!(
-                    "  {} -> /home/claude/workspace/{}",
-                    abs.display().to_string().blue(),
-                    fname
-                );
-            }
+    let mut conversations: Vec<_> = conversations.into_iter().collect();
+    conversations.sort_by_key(|(_, (mtime, _))| std::cmp::Reverse(*mtime));
 
-            if !config.ports.is_empty() {
-                println!("{}:", "Exposed ports".bold());
-                for port in &config.ports {
-                    let normalized = normalize_port_mapping(port)?;
-                    println!("  {} {}", "→".green(), normalized);
-                }
+    let mut removed = 0;
+    let mut freed = 0;
+    for (id, (mtime, paths)) in conversations.into_iter().skip(keep) {
+        if cutoff.is_some_and(|cutoff| mtime > cutoff) {
+            continue;
+        }
+        for path in paths {
+            freed += path_size(&path);
+            if path.is_dir() {
+                std::fs::remove_dir_all(&path)?;
+            } else {
+                std::fs::remove_file(&path)?;
             }
-
-            start_container(
-                &container_name,
-                &config.folders,
-                config.memory.as_deref(),
-                config.cpus.as_deref(),
-                &config.ports,
-                &config.env_vars,
-            )
-            .await?;
-
-            // Register the container with its folders
-            register_container(&container_name, &config.folders)?;
         }
+        let modified: chrono::DateTime<chrono::Local> = mtime.into();
+        println!(
+            "  {} {} (last modified {})",
+            "-".red(),
+            id,
+            modified.format("%Y-%m-%d %H:%M")
+        );
+        removed += 1;
     }
 
-    // Save the current container as the last used session
-    save_last_session(&container_name)?;
-
-    let final_prompt = match (config.prompt, config.prompt_file) {
-        (Some(p), _) => Some(p),
-        (None, Some(f)) => Some(std::fs::read_to_string(&f)?),
-        (None, None) => None,
-    };
+    if removed == 0 {
+        println!("No conversations to remove for container '{}'.", container);
+    } else {
+        println!(
+            "{} Removed {} conversation(s), freed {}",
+            "✓".green(),
+            removed,
+            format_size(freed)
+        );
+    }
+    Ok(())
+}
 
-    print_banner(
-        &container_name,
-        config.session_name.as_deref(),
-        &config.ports,
-        &config.folders,
+fn show_history(container: &str, show_prompts: bool) -> Result<()> {
+    let path = get_container_config_dir(container)?.join("history.jsonl");
+    if !path.exists() {
+        println!("No history recorded for container '{}'.", container);
+        return Ok(());
+    }
+    println!(
+        "{}",
+        format!("History for container '{}':", container).bold()
     );
-
-    exec_claude_interactive(
-        &container_name,
-        final_prompt.as_deref(),
-        config.dangerously_skip_permissions,
-        config.continue_session,
-        config.resume.as_deref(),
-    )?;
-
-    // If this was a named session, detect and save the conversation ID
-    if let Some(ref session_name) = config.session_name {
-        if let Some(conv_id) = detect_latest_conversation_id(&container_name).await? {
-            save_named_session(session_name, &conv_id)?;
-            println!(
-                "\n{} Session '{}' saved (conversation: {})",
-                "✓".green(),
-                session_name,
-                &conv_id[..8.min(conv_id.len())]
-            );
-        } else {
+    for line in std::fs::read_to_string(&path)?.lines() {
+        let Ok(entry) = serde_json::from_str::<HistoryEntry>(line) else {
+            continue;
+        };
+        let mut details = Vec::new();
+        if let Some(name) = entry.session_name {
+            details.push(format!("session={}", name));
+        }
+        if let Some(id) = entry.conversation_id {
+            details.push(format!("conversation={}", id));
+        }
+        match entry.prompt {
+            Some(p) if show_prompts => details.push(format!("prompt={:?}", p)),
+            Some(_) => details.push("prompt=<redacted>".to_string()),
+            None => {}
+        }
+        println!(
+            "  {} {:<8} {}",
+            entry.timestamp.blue(),
+            entry.action.green(),
+            details.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// Print a short card to re-orient in a long-lived sandbox
+async fn describe_container(container: &str, show_prompts: bool) -> Result<()> {
+    check_docker().await?;
+    let entry = lookup_container_entry(container)?.map(|(_, entry)| entry);
+    let running = container_running(container).await?;
+    let state = if running {
+        "● running".green()
+    } else if container_exists(container).await? {
+        "○ stopped".red()
+    } else {
+        "✗ not created".red()
+    };
+    let alias = entry
+        .as_ref()
+        .and_then(|e| e.alias.as_deref())
+        .map(|a| format!(" ({})", a))
+        .unwrap_or_default();
+    let bar = "│".cyan();
+
+    println!(
+        "{} {}{}  {}",
+        "╭".cyan(),
+        container.bold(),
+        alias.yellow(),
+        state
+    );
+    if let Some(ref entry) = entry {
+        println!("{} Created:        {}", bar, entry.created_at);
+        if let Some(ref note) = entry.note {
+            println!("{} Note:           {}", bar, note);
+        }
+    }
+    // The first recorded prompt usually says what the sandbox was set up for
+    let history = get_container_config_dir(container)?.join("history.jsonl");
+    let first_prompt = std::fs::read_to_string(history)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str::<HistoryEntry>(line).ok())
+        .find_map(|h| h.prompt);
+    match first_prompt {
+        Some(prompt) if show_prompts => {
+            let line = prompt
+                .lines()
+                .find(|l| !l.trim().is_empty())
+                .unwrap_or_default();
             println!(
-                "\n{} Could not detect conversation ID for session '{}'",
-                "⚠".yellow(),
-                session_name
+                "{} First prompt:   {}",
+                bar,
+                truncate_chars(line.trim(), 60)
             );
         }
+        Some(_) => println!(
+            "{} First prompt:   {}",
+            bar,
+            "<redacted> (--show-prompts to see it)".dimmed()
+        ),
+        None => {}
+    }
+    let conversations = list_conversations(container)?;
+    if let Some(title) = conversations.first().and_then(|c| c.title.as_deref()) {
+        println!("{} Latest:         {}", bar, title);
     }
+    println!("{} Conversations:  {}", bar, conversations.len());
 
-    println!("\n{} Exited Claude session", "✓".green());
-    println!("  Container '{}' is still running", container_name);
-    // Show how to reconnect
-    let folder_hint = config
-        .folders
-        .first()
-        .and_then(|f| f.to_str())
-        .unwrap_or(&container_name);
-    if let Some(ref name) = config.session_name {
-        println!(
-            "  Use {} to resume this session",
-            format!("claude-sandbox continue {} -n {}", folder_hint, name).yellow()
-        );
-    } else {
+    let folders = entry.map(|e| e.folder_paths).unwrap_or_default();
+    if !folders.is_empty() {
+        println!("{} Folders:", bar);
+    }
+    for folder in &folders {
+        let fname = Path::new(folder)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let git = if running {
+            git_summary(container, &format!("/home/claude/workspace/{}", fname)).await
+        } else {
+            None
+        };
+        match git {
+            Some(summary) => println!("{}   {}  {}", bar, folder.blue(), summary),
+            None => println!("{}   {}", bar, folder.blue()),
+        }
+    }
+    if !running && !folders.is_empty() {
         println!(
-            "  Use {} to continue",
-            format!("claude-sandbox continue {}", folder_hint).yellow()
+            "{} {}",
+            bar,
+            "(start the container to see git details)".dimmed()
         );
     }
-
+    println!("{}", "╰".cyan());
     Ok(())
 }
 
-async fn continue_session_cmd(container: &str, session_name: Option<&str>) -> Result<()> {
-    check_docker().await?;
+/// `branch @ <hash> <subject> (<age>)` for a git checkout in the container
+async fn git_summary(container: &str, dir: &str) -> Option<String> {
+    let git = |args: &[&str]| {
+        let mut cmd = Command::new("docker");
+        cmd.args([
+            "exec",
+            container,
+            "git",
+            "-c",
+            "safe.directory=*",
+            "-C",
+            dir,
+        ])
+        .args(args)
+        .stderr(Stdio::null())
+        .traced();
+        cmd
+    };
+    let branch = git(&["rev-parse", "--abbrev-ref", "HEAD"])
+        .output()
+        .await
+        .ok()?;
+    if !branch.status.success() {
+        return None;
+    }
+    let commit = git(&["log", "-1", "--format=%h %s (%cr)"])
+        .output()
+        .await
+        .ok()?;
+    Some(format!(
+        "{} @ {}",
+        String::from_utf8_lossy(&branch.stdout).trim().green(),
+        truncate_chars(String::from_utf8_lossy(&commit.stdout).trim(), 70)
+    ))
+}
 
-    if !container_running(container).await? {
-        bail!("Container '{container}' is not running. Use 'run' to start it.");
+/// Shorten `s` to at most `max` characters, marking the cut with an ellipsis
+fn truncate_chars(s: &str, max: usize) -> String {
+    if s.chars().count() > max {
+        format!("{}…", s.chars().take(max - 1).collect::<String>())
+    } else {
+        s.to_string()
     }
+}
 
-    // Save as last used session
-    save_last_session(container)?;
+async fn status_container(container: &str) -> Result<()> {
+    check_docker().await?;
+    if !container_exists(container).await? {
+        println!("{} Container '{}' does not exist", "✗".red(), container);
+        return Ok(());
+    }
+    print_status(container, &inspect_state(container).await?);
+    Ok(())
+}
 
-    // If a named session is provided, look up the conversation ID and resume
-    if let Some(name) = session_name {
-        let conversation_id = get_named_session(name)?.ok_or_else(|| {
-            anyhow::anyhow!(
-                "Named session '{}' not found. Use 'run -n {}' to create it.",
-                name,
-                name
-            )
-        })?;
+/// Print the status line of every sandbox container
+async fn status_all() -> Result<()> {
+    check_docker().await?;
+    let containers = list_sandbox_containers(false).await?;
+    if containers.is_empty() {
+        println!("No sandbox containers found.");
+        return Ok(());
+    }
+    for container in &containers {
+        // The container may be removed between listing and inspecting it
+        if let Ok(state) = inspect_state(container).await {
+            print_status(container, &state);
+        }
+    }
+    Ok(())
+}
 
+fn print_status(container: &str, state: &ContainerState) {
+    let icon = if state.paused {
+        "❚".yellow()
+    } else if state.running {
+        "●".green()
+    } else {
+        "○".red()
+    };
+    println!("{} Container '{}': {}", icon, container, state.status);
+    if let Ok(Some((_, entry))) = lookup_container_entry(container) {
+        if let Some(note) = entry.note {
+            println!("  {} {}", "Note:".dimmed(), note);
+        }
+    }
+    if state.oom_killed {
         println!(
             "{}",
-            format!(
-                "Resuming session '{}' (conversation: {}) in container '{}'...",
-                name,
-                &conversation_id[..8.min(conversation_id.len())],
-                container
-            )
-            .cyan()
+            "  ⚠ The container was killed by the OOM killer. Consider raising --memory \
+             (and --memory-swap) when recreating it."
+                .red()
         );
+    }
+}
 
-        exec_claude_interactive(container, None, false, false, Some(&conversation_id))?;
-
-        println!("\n{} Exited session '{}'", "✓".green(), name);
-    } else {
+/// Add a folder to a container's mounts by recreating it. Conversation
+/// history lives on the host and survives the recreate.
+async fn add_folder(container: &str, folder: PathBuf, force: bool) -> Result<()> {
+    check_docker().await?;
+    let (key, entry) = lookup_container_entry(container)?.with_context(|| {
+        format!(
+            "Container '{}' is not in the folder registry. Use 'run' to create it.",
+            container
+        )
+    })?;
+    let (abs, _) = resolve_folder_path(&folder)?;
+    let abs_str = abs.to_string_lossy().to_string();
+    if entry.folder_paths.contains(&abs_str) {
         println!(
-            "{}",
-            format!("Continuing last conversation in container '{container}'...").cyan()
+            "Folder '{}' is already mounted in '{}'.",
+            abs.display(),
+            container
         );
+        return Ok(());
+    }
 
-        exec_claude_interactive(container, None, false, true, None)?;
+    let mut folders: Vec<PathBuf> = entry.folder_paths.iter().map(PathBuf::from).collect();
+    folders.push(abs);
 
-        println!("\n{} Exited Claude session", "✓".green());
+    println!(
+        "{}",
+        format!(
+            "Docker cannot add mounts to an existing container, so '{}' will be recreated.",
+            container
+        )
+        .yellow()
+    );
+    println!(
+        "Running processes will be lost. Conversation history, env vars and the options \
+         from the original 'run' (ports, limits, network, image) are kept."
+    );
+    if !force && !confirm("Recreate the container?")? {
+        println!("Aborted.");
+        return Ok(());
     }
 
-    println!("  Container '{container}' is still running");
+    recreate_container(container, &key, &entry, &folders).await?;
 
+    println!(
+        "{} Container '{}' recreated with folders:",
+        "✓".green(),
+        container
+    );
+    for folder in &folders {
+        println!("  {} {}", "→".green(), folder.display());
+    }
     Ok(())
 }
 
-async fn resume_session_cmd(container: &str, conversation: Option<&str>) -> Result<()> {
-    check_docker().await?;
+/// Replace a registered container with a new one mounting `folders`, created
+/// with the stored options and the entry's env vars, then update its registry
+/// entry (alias, note and expiry are kept)
+async fn recreate_container(
+    container: &str,
+    key: &str,
+    entry: &ContainerEntry,
+    folders: &[PathBuf],
+) -> Result<()> {
+    let mut options = match entry.options.clone() {
+        Some(options) => options,
+        None if container_exists(container).await? => bail!(
+            "'{}' was created by an older version of claude-sandbox that didn't record its \
+             options (ports, limits, network, image), so it can't be recreated without losing \
+             them. Run it once with 'run --recreate' and its original options first.",
+            container
+        ),
+        // Nothing to preserve
+        None => ContainerOptions::default(),
+    };
+    options.env_vars = entry.env_vars.clone();
+    if container_exists(container).await? {
+        Command::new("docker")
+            .args(["rm", "-f", container])
+            .stdout(Stdio::null())
+            .traced()
+            .status()
+            .await?;
+    }
+    start_container(container, folders, &options).await?;
 
-    if !container_running(container).await? {
-        bail!("Container '{container}' is not running. Use 'run' to start it.");
+    let _lock = lock_registry("folder_registry")?;
+    let mut registry = load_folder_registry()?;
+    registry.folders.remove(key);
+    registry.folders.insert(
+        folder_key(folders)?,
+        ContainerEntry {
+            folder_paths: folders
+                .iter()
+                .filter_map(|f| f.canonicalize().ok())
+                .map(|p| p.to_string_lossy().to_string())
+                .collect(),
+            created_at: chrono::Local::now().to_rfc3339(),
+            options: Some(options),
+            ..entry.clone()
+        },
+    );
+    save_folder_registry(&registry)
+}
+
+/// Show the registry entry and `docker inspect` data of a container, secrets hidden
+async fn info_container(container: &str, json: bool) -> Result<()> {
+    check_docker().await?;
+    // Stored `-e` values end up in the JSON report, so they are redacted too
+    let entry = lookup_container_entry(container)?.map(|(_, mut entry)| {
+        entry.env_vars = entry.env_vars.iter().map(|v| redact_env(v)).collect();
+        entry
+    });
+    let output = Command::new("docker")
+        .args(["inspect", container])
+        .traced()
+        .output()
+        .await?;
+    let inspect: Option<serde_json::Value> = if output.status.success() {
+        serde_json::from_slice::<Vec<serde_json::Value>>(&output.stdout)?
+            .into_iter()
+            .next()
+    } else {
+        None
+    };
+    if entry.is_none() && inspect.is_none() {
+        bail!(CliError::new(
+            ErrorKind::NotFound,
+            format!(
+                "Container '{}' does not exist and is not in the folder registry",
+                container
+            )
+        ));
     }
 
-    // Save as last used container
-    save_last_session(container)?;
+    let report = match &inspect {
+        Some(i) => {
+            let state = &i["State"];
+            let mounts: Vec<serde_json::Value> = i["Mounts"]
+                .as_array()
+                .map(|mounts| {
+                    mounts
+                        .iter()
+                        .map(|m| {
+                            serde_json::json!({
+                                "source": m["Source"],
+                                "destination": m["Destination"],
+                                "read_only": !m["RW"].as_bool().unwrap_or(true),
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let env: Vec<String> = i["Config"]["Env"]
+                .as_array()
+                .map(|env| {
+                    env.iter()
+                        .filter_map(|e| e.as_str())
+                        .map(redact_env)
+                        .collect()
+                })
+                .unwrap_or_default();
+            serde_json::json!({
+                "container": container,
+                "registry": entry,
+                "exists": true,
+                "image": i["Config"]["Image"],
+                "image_id": i["Image"],
+                "created": i["Created"],
+                "state": {
+                    "status": state["Status"],
+                    "running": state["Running"],
+                    "paused": state["Paused"],
+                    "oom_killed": state["OOMKilled"],
+                    "exit_code": state["ExitCode"],
+                    "started_at": state["StartedAt"],
+                },
+                "mounts": mounts,
+                "ports": if state["Running"].as_bool().unwrap_or(false) {
+                    published_ports(container).await?
+                } else {
+                    Vec::new()
+                },
+                "env": env,
+                "labels": i["Config"]["Labels"],
+                "restart_policy": i["HostConfig"]["RestartPolicy"]["Name"],
+                "network": i["HostConfig"]["NetworkMode"],
+            })
+        }
+        None => serde_json::json!({
+            "container": container,
+            "registry": entry,
+            "exists": false,
+        }),
+    };
 
-    if let Some(c) = conversation {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&Versioned::new(report))?);
+        return Ok(());
+    }
+
+    let text = |v: &serde_json::Value| v.as_str().unwrap_or("-").to_string();
+    println!("{}", format!("Container '{}'", container).bold());
+    println!("{}", "Registry:".bold());
+    match &entry {
+        Some(entry) => {
+            println!("  Created:  {}", entry.created_at);
+            if let Some(ref alias) = entry.alias {
+                println!("  Alias:    {}", alias);
+            }
+            println!("  Folders:");
+            for folder in &entry.folder_paths {
+                println!("    {}", folder);
+            }
+        }
+        None => println!("  (not in the folder registry)"),
+    }
+    if inspect.is_none() {
         println!(
-            "{}",
-            format!("Resuming conversation '{c}' in container '{container}'...").cyan()
+            "{} The container does not exist (run it again to recreate it)",
+            "✗".red()
         );
-    } else {
+        return Ok(());
+    }
+    let state = &report["state"];
+    println!("{}", "Docker:".bold());
+    println!("  Status:   {}", text(&state["status"]));
+    if state["oom_killed"].as_bool().unwrap_or(false) {
+        println!("  {}", "Killed by the OOM killer".red());
+    }
+    println!(
+        "  Image:    {} ({})",
+        text(&report["image"]),
+        text(&report["image_id"])
+    );
+    println!("  Created:  {}", text(&report["created"]));
+    println!("  Network:  {}", text(&report["network"]));
+    println!("  Restart:  {}", text(&report["restart_policy"]));
+    println!("{}", "Mounts:".bold());
+    for m in report["mounts"].as_array().into_iter().flatten() {
+        let mode = if m["read_only"].as_bool().unwrap_or(false) {
+            " (ro)"
+        } else {
+            ""
+        };
         println!(
-            "{}",
-            format!("Opening conversation picker in container '{container}'...").cyan()
+            "  {} -> {}{}",
+            text(&m["source"]),
+            text(&m["destination"]),
+            mode
         );
     }
+    println!("{}", "Ports:".bold());
+    for port in report["ports"].as_array().into_iter().flatten() {
+        println!("  {}", text(port));
+    }
+    println!("{}", "Env:".bold());
+    for var in report["env"].as_array().into_iter().flatten() {
+        println!("  {}", text(var));
+    }
+    println!("{}", "Labels:".bold());
+    if let Some(labels) = report["labels"].as_object() {
+        let mut labels: Vec<_> = labels.iter().collect();
+        labels.sort_by_key(|(k, _)| k.as_str());
+        for (key, value) in labels {
+            println!("  {}={}", key, text(value));
+        }
+    }
+    Ok(())
+}
 
-    // If no conversation specified, claude -r will show interactive picker
-    exec_claude_interactive(container, None, false, false, conversation.or(Some("")))?;
-
-    println!("\n{} Exited Claude session", "✓".green());
-    println!("  Container '{container}' is still running");
-
+/// Print the env vars stored for a container, hiding secret-looking values
+fn show_env(container: &str) -> Result<()> {
+    let (_, entry) = lookup_container_entry(container)?
+        .with_context(|| format!("Container '{}' is not in the folder registry", container))?;
+    if entry.env_vars.is_empty() {
+        println!("No env vars stored for container '{}'.", container);
+        return Ok(());
+    }
+    for var in &entry.env_vars {
+        println!("{}", redact_env(var));
+    }
     Ok(())
 }
 
-async fn shell_container(container: &str) -> Result<()> {
+/// Set (`KEY=VALUE`) or remove (`unset`) stored env vars and recreate the
+/// container so they take effect
+async fn set_env(container: &str, vars: &[String], unset: &[String], force: bool) -> Result<()> {
     check_docker().await?;
-    if !container_running(container).await? {
-        bail!("Container '{container}' is not running");
+    let (key, mut entry) = lookup_container_entry(container)?.with_context(|| {
+        format!(
+            "Container '{}' is not in the folder registry. Use 'run' to create it.",
+            container
+        )
+    })?;
+    for var in vars {
+        match var.split_once('=') {
+            Some((name, _)) if !name.is_empty() => {}
+            _ => bail!("Invalid env var '{}': expected KEY=VALUE", var),
+        }
     }
-    // Save as last used container
-    save_last_session(container)?;
+
+    let name_of = |var: &String| {
+        var.split_once('=')
+            .map_or(var.clone(), |(k, _)| k.to_string())
+    };
+    let replaced: Vec<String> = vars
+        .iter()
+        .map(name_of)
+        .chain(unset.iter().cloned())
+        .collect();
+    entry.env_vars.retain(|v| !replaced.contains(&name_of(v)));
+    entry.env_vars.extend(vars.iter().cloned());
+
     println!(
         "{}",
-        format!("Opening shell in container '{container}'...").cyan()
+        format!(
+            "'{}' will be recreated so the new environment takes effect.",
+            container
+        )
+        .yellow()
+    );
+    println!(
+        "Running processes will be lost. Conversation history and the options from the \
+         original 'run' (ports, limits, network, image) are kept."
+    );
+    if !force && !confirm("Recreate the container?")? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let folders: Vec<PathBuf> = entry.folder_paths.iter().map(PathBuf::from).collect();
+    recreate_container(container, &key, &entry, &folders).await?;
+    println!(
+        "{} Container '{}' recreated with {} env var(s)",
+        "✓".green(),
+        container,
+        entry.env_vars.len()
     );
-    std::process::Command::new("docker")
-        .args(["exec", "-it", container, "bash"])
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()?;
     Ok(())
 }
 
-async fn stop_container(container: &str) -> Result<()> {
+/// Start the services listed in the folder's services file on a dedicated
+/// network, then run the sandbox attached to that network
+async fn up_stack(folder: PathBuf) -> Result<()> {
+    let services_path = folder.join(SERVICES_FILE);
+    let content = std::fs::read_to_string(&services_path)
+        .with_context(|| format!("Cannot read {}", services_path.display()))?;
+    let spec: ServicesFile = toml::from_str(&content)
+        .with_context(|| format!("Invalid services file: {}", services_path.display()))?;
+
     check_docker().await?;
-    if !container_exists(container).await? {
-        bail!("Container '{container}' does not exist");
-    }
-    println!("{}", format!("Stopping container '{container}'...").cyan());
-    Command::new("docker")
-        .args(["stop", container])
+    let container_name = derive_container_name(std::slice::from_ref(&folder))?;
+    let network = format!("{}-net", container_name);
+
+    let network_exists = Command::new("docker")
+        .args(["network", "inspect", &network])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .traced()
         .status()
+        .await?
+        .success();
+    if !network_exists {
+        println!("{}", format!("Creating network '{}'...", network).cyan());
+        let output = Command::new("docker")
+            .args(["network", "create", &network])
+            .traced()
+            .output()
+            .await?;
+        if !output.status.success() {
+            bail!(
+                "Failed to create network: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+    }
+
+    let mut env_vars = Vec::new();
+    for service in &spec.services {
+        let service_container = format!("{}-{}", container_name, sanitize_name(&service.name));
+        env_vars.push(format!(
+            "{}_HOST={}",
+            service.name.to_uppercase().replace('-', "_"),
+            service.name
+        ));
+        if container_running(&service_container).await? {
+            println!("  {} '{}' already running", "→".green(), service.name);
+            continue;
+        }
+        if container_exists(&service_container).await? {
+            Command::new("docker")
+                .args(["rm", "-f", &service_container])
+                .stdout(Stdio::null())
+                .traced()
+                .status()
+                .await?;
+        }
+        println!(
+            "  {} Starting service '{}' ({})",
+            "→".green(),
+            service.name,
+            service.image
+        );
+        let mut args = vec![
+            "run".to_string(),
+            "-d".to_string(),
+            "--name".to_string(),
+            service_container,
+            "--network".to_string(),
+            network.clone(),
+            "--network-alias".to_string(),
+            service.name.clone(),
+            "--label".to_string(),
+            format!("{}={}", STACK_LABEL, container_name),
+        ];
+        for port in expand_port_ranges(&service.ports)? {
+            args.extend(["-p".to_string(), normalize_port_mapping(&port)?]);
+        }
+        for e in &service.env {
+            args.extend(["-e".to_string(), e.clone()]);
+        }
+        args.push(service.image.clone());
+        let output = Command::new("docker").args(&args).traced().output().await?;
+        if !output.status.success() {
+            bail!(
+                "Failed to start service '{}': {}",
+                service.name,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+    }
+
+    // run_claude attaches to a running sandbox as-is, so make sure it can
+    // reach the services (the *_HOST variables only reach new containers)
+    if container_running(&container_name).await? {
+        connect_to_network(&container_name, &network).await?;
+    }
+
+    run_claude(RunConfig {
+        folders: vec![folder],
+        container: ContainerOptions {
+            env_vars,
+            network: Some(network),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await
+}
+
+/// Attach a running container to a network unless it is already on it
+async fn connect_to_network(container: &str, network: &str) -> Result<()> {
+    let output = Command::new("docker")
+        .args([
+            "inspect",
+            "-f",
+            "{{range $name, $_ := .NetworkSettings.Networks}}{{$name}}\n{{end}}",
+            container,
+        ])
+        .traced()
+        .output()
         .await?;
-    Command::new("docker")
-        .args(["rm", container])
-        .status()
+    if !output.status.success() {
+        bail!(
+            "Failed to inspect container '{}': {}",
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    if String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|n| n == network)
+    {
+        return Ok(());
+    }
+    println!(
+        "{}",
+        format!("Connecting '{}' to network '{}'...", container, network).cyan()
+    );
+    let output = Command::new("docker")
+        .args(["network", "connect", network, container])
+        .traced()
+        .output()
         .await?;
-    println!("{} Container stopped and removed", "✓".green());
+    if !output.status.success() {
+        bail!(
+            "Failed to connect '{}' to network '{}': {}",
+            container,
+            network,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
     Ok(())
 }
 
-async fn stop_all_containers() -> Result<()> {
+/// Remove a stack created by `up`: the sandbox, its services and network
+async fn down_stack(folder: PathBuf) -> Result<()> {
     check_docker().await?;
-    println!("{}", "Stopping all Claude sandbox containers...".cyan());
+    let container_name = derive_container_name(std::slice::from_ref(&folder))?;
+
+    if container_exists(&container_name).await? {
+        stop_container(&container_name, &StopOptions::default()).await?;
+    }
 
-    // Get all containers using our image
     let output = Command::new("docker")
         .args([
             "ps",
             "-a",
             "--filter",
-            &format!("ancestor={IMAGE_NAME}"),
+            &format!("label={}={}", STACK_LABEL, container_name),
             "--format",
             "{{.Names}}",
         ])
+        .traced()
         .output()
         .await?;
-
-    let output_str = String::from_utf8_lossy(&output.stdout).to_string();
-    let containers: Vec<&str> = output_str.lines().filter(|s| !s.is_empty()).collect();
-
-    if containers.is_empty() {
-        println!("No containers to stop.");
-        return Ok(());
-    }
-
-    for container in &containers {
-        println!("  Removing '{}'...", container);
-        // Stop if running, then remove
-        let _ = Command::new("docker")
-            .args(["stop", container])
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
+    for service in String::from_utf8_lossy(&output.stdout).lines() {
+        println!("  Removing service '{}'...", service);
+        Command::new("docker")
+            .args(["rm", "-f", service])
+            .stdout(Stdio::null())
+            .traced()
             .status()
-            .await;
-        let _ = Command::new("docker")
-            .args(["rm", "-f", container])
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .status()
-            .await;
+            .await?;
     }
 
-    println!("{} Removed {} container(s)", "✓".green(), containers.len());
+    let network = format!("{}-net", container_name);
+    let _ = Command::new("docker")
+        .args(["network", "rm", &network])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .traced()
+        .status()
+        .await;
+    println!("{} Stack '{}' removed", "✓".green(), container_name);
     Ok(())
 }
 
-async fn list_sessions() -> Result<()> {
-    check_docker().await?;
-    println!("{}", "Claude sandbox containers:".bold());
-    let output = Command::new("docker")
-        .args([
-            "ps",
-            "-a",
-            "--filter",
-            &format!("ancestor={IMAGE_NAME}"),
-            "--format",
-            "table {{.Names}}\t{{.Status}}\t{{.Ports}}\t{{.CreatedAt}}",
-        ])
-        .output()
-        .await?;
-    print!("{}", String::from_utf8_lossy(&output.stdout));
-
-    // Show the last used container
-    if let Ok(last) = get_last_session() {
-        println!("\n{}: {}", "Last used container".cyan(), last.green());
-    }
+/// Bash wrapper that completes TARGET arguments from `__complete_containers`
+/// and defers everything else to the clap-generated `_claude-sandbox`.
+const BASH_DYNAMIC_COMPLETION: &str = r#"
+_claude_sandbox_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    if [[ "${prev}" == "-t" || "${prev}" == "--target" ]] ||
+        [[ ${COMP_CWORD} -eq 2 && "${cur}" != -* && "${COMP_WORDS[1]}" =~ ^(continue|shell|stop|status)$ ]]; then
+        COMPREPLY=($(compgen -W "$(claude-sandbox __complete_containers 2>/dev/null)" -- "${cur}"))
+        return 0
+    fi
+    _claude-sandbox "$@"
+}
+complete -F _claude_sandbox_dynamic -o bashdefault -o default claude-sandbox
+"#;
 
-    // Show folder mappings
-    let folder_registry = load_folder_registry()?;
-    if !folder_registry.folders.is_empty() {
-        println!("\n{}", "Folder mappings:".bold());
-        for entry in folder_registry.folders.values() {
-            let folders_str = entry
-                .folder_paths
-                .iter()
-                .map(|p| {
-                    PathBuf::from(p)
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or(p)
-                        .to_string()
-                })
-                .collect::<Vec<_>>()
-                .join(", ");
-            println!(
-                "  {} {} [{}]",
-                entry.container_name.green(),
-                "←".cyan(),
-                folders_str.blue()
-            );
-        }
-    }
+/// Zsh counterpart of `BASH_DYNAMIC_COMPLETION`
+const ZSH_DYNAMIC_COMPLETION: &str = r#"
+_claude_sandbox_dynamic() {
+    if [[ ${words[CURRENT-1]} == (-t|--target) ]] ||
+        { (( CURRENT == 3 )) && [[ ${words[2]} == (continue|shell|stop|status) && ${words[CURRENT]} != -* ]] }; then
+        local -a targets
+        targets=(${(f)"$(claude-sandbox __complete_containers 2>/dev/null)"})
+        compadd -a targets
+        _files -/
+        return
+    fi
+    _claude-sandbox "$@"
+}
+compdef _claude_sandbox_dynamic claude-sandbox
+"#;
 
-    // Show named sessions
-    let registry = load_sessions_registry()?;
-    if !registry.sessions.is_empty() {
-        println!("\n{}", "Named sessions:".bold());
-        for (name, conv_id) in &registry.sessions {
-            println!(
-                "  {} -> {}",
-                name.green(),
-                &conv_id[..8.min(conv_id.len())].blue()
-            );
-        }
+fn print_completions(shell: Shell) {
+    let mut cmd = Cli::command();
+    generate(shell, &mut cmd, "claude-sandbox", &mut io::stdout());
+    match shell {
+        Shell::Bash => print!("{}", BASH_DYNAMIC_COMPLETION),
+        Shell::Zsh => print!("{}", ZSH_DYNAMIC_COMPLETION),
+        _ => {}
     }
-    Ok(())
 }
 
-fn reset_state(force: bool) -> Result<()> {
-    let config_dir = get_config_dir()?;
-    if !force {
-        println!(
-            "{}",
-            "This will delete all Claude sandbox state and memory.".yellow()
-        );
-        println!("Config directory: {}", config_dir.display());
-        print!("Continue? [y/N] ");
-        io::stdout().flush()?;
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        if !input.trim().eq_ignore_ascii_case("y") {
-            println!("Aborted.");
-            return Ok(());
+/// Print completion candidates for TARGET arguments: sandbox container
+/// names, aliases and the basenames of registered folders. Errors (e.g.
+/// Docker not running) just produce fewer candidates.
+async fn complete_containers() -> Result<()> {
+    let mut candidates = list_sandbox_containers(false).await.unwrap_or_default();
+    if let Ok(registry) = load_folder_registry() {
+        for entry in registry.folders.values() {
+            candidates.push(entry.container_name.clone());
+            candidates.extend(entry.alias.clone());
+            candidates.extend(entry.folder_paths.iter().filter_map(|p| {
+                PathBuf::from(p)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+            }));
         }
     }
-    if config_dir.exists() {
-        std::fs::remove_dir_all(&config_dir)?;
-        println!("{} State reset successfully", "✓".green());
-    } else {
-        println!("No state to reset.");
+    candidates.sort();
+    candidates.dedup();
+    for candidate in candidates {
+        println!("{}", candidate);
     }
     Ok(())
 }
 
-async fn status_container(container: &str) -> Result<()> {
-    check_docker().await?;
-    if !container_exists(container).await? {
-        println!("{} Container '{}' does not exist", "✗".red(), container);
-        return Ok(());
-    }
-    let output = Command::new("docker")
-        .args(["inspect", container])
-        .output()
-        .await?;
-    let info: Vec<ContainerInfo> = serde_json::from_slice(&output.stdout)?;
-    if let Some(i) = info.first() {
-        let icon = if i.state.running {
-            "●".green()
-        } else {
-            "○".red()
-        };
-        println!("{} Container '{}': {}", icon, container, i.state.status);
+#[tokio::main]
+async fn main() {
+    if let Err(err) = run_cli().await {
+        eprintln!("Error: {:?}", err);
+        std::process::exit(exit_code(&err));
     }
-    Ok(())
 }
 
-fn print_completions(shell: Shell) {
-    let mut cmd = Cli::command();
-    generate(shell, &mut cmd, "claude-sandbox", &mut io::stdout());
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
+async fn run_cli() -> Result<()> {
     let cli = Cli::parse();
+    if let Some(dir) = cli.config_dir {
+        // Made absolute so it means the same in docker -v specs and after a cd
+        let dir = std::path::absolute(&dir)
+            .with_context(|| format!("Invalid --config-dir '{}'", dir.display()))?;
+        let _ = CONFIG_DIR_OVERRIDE.set(dir);
+    }
+    let _ = VERBOSE.set(cli.verbose);
+    let _ = PROMPT_MODE.set(if cli.yes {
+        PromptMode::AssumeYes
+    } else if cli.no_interactive {
+        PromptMode::NoInteractive
+    } else {
+        PromptMode::Ask
+    });
+    if let Some(context) = cli.context.or(load_settings()?.docker_context) {
+        use_docker_context(&context)?;
+    }
+    if let Some(keys) = cli.detach_keys.or(load_settings()?.detach_keys) {
+        validate_detach_keys(&keys)?;
+        let _ = DETACH_KEYS.set(keys);
+    }
     match cli.command {
         Commands::Run {
             folders,
+            copy_in,
+            scratch,
+            shared_tmp,
+            no_default_mounts,
+            no_default_folders,
+            entrypoint,
             prompt,
             prompt_file,
+            prompt_template,
+            vars,
+            allow_unfilled,
             name,
             container,
+            image,
+            alias,
+            note,
             memory,
+            memory_swap,
+            memory_reservation,
             cpus,
+            cpuset_cpus,
+            pids_limit,
             ports,
+            add_host,
+            proxy,
+            inherit_proxy,
             env,
+            label,
+            label_file,
+            env_secret,
+            api_key_file,
+            api_key_keychain,
             dangerously_skip_permissions,
+            import_auth,
             continue_session,
             resume,
+            from_export,
+            replay,
+            cwd,
+            restart_policy,
+            match_user,
+            group_add,
+            max_containers,
+            stop_others,
+            ttl,
+            attach_only,
+            recreate,
+            attach_timeout,
+            json_logs,
+            print,
+            summary,
+            no_banner,
+            log_file,
+            post_start,
+            post_start_ignore_errors,
+            no_git_safe,
+            on_exit,
+            output_dir,
+            output_from,
+            stream_prompt,
+            isolated,
+            seccomp,
+            security_opt,
         } => {
             run_claude(RunConfig {
                 folders,
+                copy_in,
                 prompt,
                 prompt_file,
+                prompt_template,
+                vars,
+                allow_unfilled,
                 session_name: name,
                 container_override: container,
-                memory,
-                cpus,
-                ports,
-                env_vars: env,
+                alias,
+                container: ContainerOptions {
+                    image,
+                    memory,
+                    memory_swap,
+                    memory_reservation,
+                    cpus,
+                    cpuset_cpus,
+                    pids_limit,
+                    ports,
+                    add_hosts: add_host,
+                    env_vars: env,
+                    labels: label,
+                    env_secrets: env_secret,
+                    api_key_file,
+                    api_key_keychain,
+                    proxy,
+                    inherit_proxy,
+                    scratch,
+                    shared_tmp,
+                    no_default_mounts,
+                    no_default_folders,
+                    entrypoint,
+                    match_user,
+                    group_add,
+                    isolated,
+                    seccomp,
+                    security_opts: security_opt,
+                    restart_policy,
+                    expires_at: ttl
+                        .as_deref()
+                        .map(|ttl| {
+                            chrono::Local::now()
+                                .checked_add_signed(parse_duration(ttl)?)
+                                .with_context(|| format!("--ttl '{}' is too large", ttl))
+                        })
+                        .transpose()?,
+                    ..Default::default()
+                },
                 dangerously_skip_permissions,
+                import_auth,
                 continue_session,
                 resume,
+                from_export,
+                replay,
+                note,
+                label_file,
+                cwd,
+                max_containers,
+                stop_others,
+                attach_only,
+                recreate,
+                attach_timeout,
+                stream_prompt,
+                post_start,
+                post_start_ignore_errors,
+                no_git_safe,
+                on_exit,
+                output_dir,
+                output_from,
+                json_logs,
+                print,
+                summary,
+                no_banner,
+                log_file,
             })
             .await
         }
-        Commands::Continue { target, name } => {
+        Commands::Continue {
+            target,
+            name,
+            cwd,
+            shell,
+            prompt,
+            summary,
+        } => {
             let container_name = resolve_target_to_container(target.as_deref())?;
-            continue_session_cmd(&container_name, name.as_deref()).await
+            continue_session_cmd(
+                &container_name,
+                name.as_deref(),
+                cwd.as_deref(),
+                shell,
+                prompt.as_deref(),
+                summary,
+            )
+            .await
         }
         Commands::Resume {
             conversation_id,
             target,
+            list,
+            json,
         } => {
             let container_name = resolve_target_to_container(target.as_deref())?;
+            if list {
+                return print_conversations(&container_name, json);
+            }
             resume_session_cmd(&container_name, conversation_id.as_deref()).await
         }
-        Commands::Shell { target } => {
+        Commands::Search {
+            query,
+            target,
+            json,
+        } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            search_conversations(&container_name, &query, json)
+        }
+        Commands::Shell {
+            target,
+            json,
+            root,
+            command,
+        } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            shell_container(&container_name, &command, json, root).await
+        }
+        Commands::WatchCmd { target, paths, cmd } => {
             let container_name = resolve_target_to_container(target.as_deref())?;
-            shell_container(&container_name).await
+            watch_cmd(&container_name, &paths, &cmd).await
         }
-        Commands::Stop { target } => {
+        Commands::Stop {
+            target,
+            select,
+            image,
+            label_filter,
+            time,
+            kill,
+            keep,
+        } => {
+            for label in &label_filter {
+                validate_label("--label-filter", label, true)?;
+            }
+            let options = StopOptions { time, kill, keep };
             // Handle "all" to stop all containers
             if target.as_deref() == Some("all") {
-                stop_all_containers().await
+                let selector = SandboxSelector {
+                    image,
+                    labels: label_filter,
+                };
+                stop_all_containers(select, &selector, &options).await
             } else {
+                if select || image.is_some() || !label_filter.is_empty() {
+                    bail!("--select, --image and --label-filter only work with 'stop all'");
+                }
                 let container_name = resolve_target_to_container(target.as_deref())?;
-                stop_container(&container_name).await
+                stop_container(&container_name, &options).await
+            }
+        }
+        Commands::Start { target } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            start_container_cmd(&container_name).await
+        }
+        Commands::Pause { target } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            pause_container(&container_name, true).await
+        }
+        Commands::Unpause { target } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            pause_container(&container_name, false).await
+        }
+        Commands::List {
+            since,
+            before,
+            running,
+            image,
+            label_filter,
+            here,
+            format,
+            count,
+            group_by,
+            sort,
+            reverse,
+        } => {
+            for label in &label_filter {
+                validate_label("--label-filter", label, true)?;
+            }
+            if let Some(ref key) = group_by {
+                validate_label("--group-by", key, true)?;
+                if key.contains('=') {
+                    bail!("Invalid --group-by '{}': expected a label key", key);
+                }
             }
+            let containers = if here {
+                Some(containers_for_folder(&std::env::current_dir()?)?)
+            } else {
+                None
+            };
+            let options = ListOptions {
+                filter: ListFilter {
+                    selector: SandboxSelector {
+                        image,
+                        labels: label_filter,
+                    },
+                    since: since.as_deref().map(parse_duration).transpose()?,
+                    before: before.as_deref().map(parse_duration).transpose()?,
+                    running,
+                    containers,
+                },
+                format,
+                count,
+                group_by,
+                sort,
+                reverse,
+            };
+            list_sessions(&options).await
+        }
+        Commands::Build {
+            no_cache,
+            quiet,
+            json,
+            tag,
+            edit,
+            proxy,
+            inherit_proxy,
+            prune_images,
+            retry,
+            build_args,
+        } => {
+            build_image(&BuildOptions {
+                tag,
+                edit,
+                proxy,
+                inherit_proxy,
+                prune_images,
+                retry,
+                build_args,
+                no_cache,
+                quiet,
+                json,
+            })
+            .await
         }
-        Commands::List => list_sessions().await,
-        Commands::Build { no_cache } => build_image(no_cache).await,
+        Commands::SaveImage { out, image } => save_image(&out, image.as_deref()).await,
+        Commands::LoadImage { archive } => load_image(&archive).await,
         Commands::Reset { force } => reset_state(force),
-        Commands::Status { target } => {
+        Commands::Connect { target, port } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            connect_container(&container_name, port).await
+        }
+        Commands::Info { target, json } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            info_container(&container_name, json).await
+        }
+        Commands::Env { target } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            show_env(&container_name)
+        }
+        Commands::SetEnv {
+            target,
+            kv,
+            unset,
+            force,
+        } => {
+            if kv.is_empty() && unset.is_empty() {
+                bail!("Nothing to change: give KEY=VALUE pairs and/or --unset KEY");
+            }
+            let container_name = resolve_target_to_container(Some(&target))?;
+            set_env(&container_name, &kv, &unset, force).await
+        }
+        Commands::Reap { dry_run } => reap_containers(dry_run).await,
+        Commands::Config { open, path } => config_dir_cmd(open, path).await,
+        Commands::CleanConversations {
+            target,
+            keep,
+            older_than,
+        } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            clean_conversations(&container_name, keep, older_than.as_deref())
+        }
+        Commands::Logs {
+            target,
+            follow,
+            tail,
+            grep,
+            invert,
+        } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            logs_cmd(&container_name, follow, tail, grep.as_deref(), invert).await
+        }
+        Commands::History {
+            target,
+            show_prompts,
+        } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            show_history(&container_name, show_prompts)
+        }
+        Commands::Describe {
+            target,
+            show_prompts,
+        } => {
+            let container_name = resolve_target_to_container(target.as_deref())?;
+            describe_container(&container_name, show_prompts).await
+        }
+        Commands::Note {
+            target,
+            text,
+            clear,
+        } => {
+            let container_name = resolve_target_to_container(Some(&target))?;
+            note_cmd(&container_name, text.as_deref(), clear)
+        }
+        Commands::Status { target, all } => {
+            if all {
+                return status_all().await;
+            }
             let container_name = resolve_target_to_container(target.as_deref())?;
             status_container(&container_name).await
         }
+        Commands::AddFolder {
+            target,
+            folder,
+            force,
+        } => {
+            let container_name = resolve_target_to_container(Some(&target))?;
+            add_folder(&container_name, folder, force).await
+        }
+        Commands::Up { folder } => up_stack(folder).await,
+        Commands::Down { folder } => down_stack(folder).await,
         Commands::Completions { shell } => {
             print_completions(shell);
             Ok(())
         }
+        Commands::CompleteContainers => complete_containers().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ports(specs: &[&str]) -> Vec<String> {
+        specs.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn expand_port_ranges_pairs_host_and_container_ranges() {
+        assert_eq!(
+            expand_port_ranges(&ports(&["8000-8002:9000-9002"])).unwrap(),
+            ports(&["8000:9000", "8001:9001", "8002:9002"])
+        );
+    }
+
+    #[test]
+    fn expand_port_ranges_keeps_single_ports() {
+        assert_eq!(
+            expand_port_ranges(&ports(&["3000", "8080:3000"])).unwrap(),
+            ports(&["3000", "8080:3000"])
+        );
+        assert_eq!(
+            expand_port_ranges(&ports(&["127.0.0.1:8000-8001:9000-9001"])).unwrap(),
+            ports(&["127.0.0.1:8000:9000", "127.0.0.1:8001:9001"])
+        );
+    }
+
+    #[test]
+    fn expand_port_ranges_rejects_reversed_ranges() {
+        assert!(expand_port_ranges(&ports(&["8002-8000"])).is_err());
+        assert!(expand_port_ranges(&ports(&["8000-8002:9002-9000"])).is_err());
+    }
+
+    #[test]
+    fn expand_port_ranges_rejects_mismatched_lengths() {
+        assert!(expand_port_ranges(&ports(&["8000-8002:9000-9001"])).is_err());
+        assert!(expand_port_ranges(&ports(&["8000-8001:9000"])).is_err());
+    }
+
+    #[test]
+    fn parse_selection_expands_ranges_and_dedups() {
+        assert_eq!(parse_selection("1", 3).unwrap(), vec![0]);
+        assert_eq!(parse_selection("1,3-5", 5).unwrap(), vec![0, 2, 3, 4]);
+        assert_eq!(parse_selection(" 3 , 1-2 ,2", 3).unwrap(), vec![0, 1, 2]);
+        assert_eq!(parse_selection("2-2", 2).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn parse_selection_of_blank_input_is_empty() {
+        assert!(parse_selection("", 3).unwrap().is_empty());
+        assert!(parse_selection(" , ,", 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_selection_rejects_out_of_range_and_malformed() {
+        for input in ["0", "4", "0-2", "2-4", "5-3", "x", "1-", "-2", "1-2-3"] {
+            assert!(parse_selection(input, 3).is_err(), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn parse_duration_accepts_each_unit() {
+        assert_eq!(
+            parse_duration("30s").unwrap(),
+            chrono::Duration::seconds(30)
+        );
+        assert_eq!(
+            parse_duration("15m").unwrap(),
+            chrono::Duration::minutes(15)
+        );
+        assert_eq!(parse_duration(" 2h ").unwrap(), chrono::Duration::hours(2));
+        assert_eq!(parse_duration("7d").unwrap(), chrono::Duration::days(7));
+        assert_eq!(parse_duration("1w").unwrap(), chrono::Duration::weeks(1));
+        assert_eq!(parse_duration("0s").unwrap(), chrono::Duration::zero());
+    }
+
+    #[test]
+    fn parse_duration_rejects_malformed_values() {
+        for value in [
+            "",
+            "30",
+            "h",
+            "-5m",
+            "1.5h",
+            "2x",
+            "2 h",
+            "2H",
+            "99999999999999999w",
+        ] {
+            assert!(parse_duration(value).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn match_snippet_finds_case_insensitive_matches() {
+        assert_eq!(
+            match_snippet("Fix the Tokio runtime", "tokio").as_deref(),
+            Some("Fix the Tokio runtime")
+        );
+        assert_eq!(match_snippet("nothing here", "tokio"), None);
+        assert_eq!(match_snippet("anything", ""), None);
+    }
+
+    #[test]
+    fn match_snippet_trims_long_text_and_collapses_whitespace() {
+        let text = format!("{}needle\n\n  here{}", "a".repeat(100), "b".repeat(100));
+        let snippet = match_snippet(&text, "NEEDLE").unwrap();
+        assert_eq!(
+            snippet,
+            format!("…{}needle here{}…", "a".repeat(40), "b".repeat(32))
+        );
+    }
+
+    #[test]
+    fn parse_memory_limit_accepts_docker_sizes() {
+        assert_eq!(parse_memory_limit("1073741824"), Some(1 << 30));
+        assert_eq!(parse_memory_limit("512b"), Some(512));
+        assert_eq!(parse_memory_limit("64k"), Some(64 << 10));
+        assert_eq!(parse_memory_limit("512m"), Some(512 << 20));
+        assert_eq!(parse_memory_limit("512MB"), Some(512 << 20));
+        assert_eq!(parse_memory_limit("4g"), Some(4 << 30));
+        assert_eq!(parse_memory_limit("4gb"), Some(4 << 30));
+        assert_eq!(parse_memory_limit("1.5g"), Some(3 << 29));
+        assert_eq!(parse_memory_limit("0.5GB"), Some(1 << 29));
+    }
+
+    #[test]
+    fn parse_memory_limit_rejects_malformed_sizes() {
+        for value in [
+            "", "g", "gb", "b", "4x", "4gg", "4bg", "-4g", "1.g", ".5g", "1.2.3g", "4 g",
+        ] {
+            assert_eq!(parse_memory_limit(value), None, "{value:?}");
+        }
+        assert!(validate_memory_limit("--memory", "4gig").is_err());
+    }
+
+    #[test]
+    fn split_entrypoint_keeps_quoted_arguments_together() {
+        assert_eq!(
+            split_entrypoint("sh -c 'while true; do sleep 1; done'").unwrap(),
+            ["sh", "-c", "while true; do sleep 1; done"]
+        );
+        assert_eq!(
+            split_entrypoint("sleep infinity").unwrap(),
+            ["sleep", "infinity"]
+        );
+        assert!(split_entrypoint("  ").is_err());
+        assert!(split_entrypoint("sh -c 'unclosed").is_err());
+    }
+
+    #[test]
+    fn malformed_yaml_registry_is_an_error_and_left_alone() {
+        let dir = std::env::temp_dir().join(format!("claude-sandbox-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("folder_registry.yaml");
+        let content = "folders:\n  abc: [unclosed\n";
+        std::fs::write(&path, content).unwrap();
+
+        let err = read_registry_file::<FolderRegistry>(&path, RegistryFormat::Yaml)
+            .err()
+            .expect("malformed registry should not parse");
+        assert!(format!("{:#}", err).contains(&path.display().to_string()));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    const ID_A: &str = "0b6f2c1e-1111-4222-8333-944455556666";
+    const ID_B: &str = "9f1d7a20-aaaa-4bbb-8ccc-dddddddddddd";
+
+    #[test]
+    fn latest_conversation_id_of_empty_listing() {
+        assert_eq!(latest_conversation_id(""), None);
+        assert_eq!(latest_conversation_id("\n\n"), None);
+    }
+
+    #[test]
+    fn latest_conversation_id_of_single_entry() {
+        let listing = format!("1700000000.5 /home/claude/.claude/projects/-w/{ID_A}.jsonl\n");
+        assert_eq!(latest_conversation_id(&listing), Some(ID_A.to_string()));
+    }
+
+    #[test]
+    fn latest_conversation_id_picks_newest() {
+        let listing = format!(
+            "1700000100.0 /p/-w/{ID_A}.jsonl\n1700000200.0 /p/-w/{ID_B}.jsonl\n1700000050.0 /p/-w/{ID_A}\n"
+        );
+        assert_eq!(latest_conversation_id(&listing), Some(ID_B.to_string()));
+    }
+
+    #[test]
+    fn latest_conversation_id_ignores_other_files() {
+        let listing = format!(
+            "1700000100.0 /p/-w/{ID_A}.jsonl\n1700000900.0 /p/-w/notes.txt\n\
+             1700000900.0 /p/-w/{ID_B}.json\n1700000900.0 /p/-w\nnot a line\n"
+        );
+        assert_eq!(latest_conversation_id(&listing), Some(ID_A.to_string()));
     }
 }